@@ -0,0 +1,73 @@
+// Shared by the benches: an allocation-counting global allocator, the sizes
+// of the workload and the dense layer it evaluates on the `Var` path.
+
+// each bench uses its own subset of these
+#![allow(dead_code)]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use micrograd::{new_var, Var};
+
+struct Counting;
+
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting{
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        return unsafe { System.alloc(layout) };
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        return unsafe { System.realloc(ptr, layout, new_size) };
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+pub const INPUTS: usize = 64;
+pub const OUTPUTS: usize = 32;
+pub const STEPS: usize = 200;
+
+pub fn allocs() -> usize{
+    return ALLOCS.load(Ordering::Relaxed);
+}
+
+/// Prints the time and allocations per step since `start` and `before`, a
+/// value of [`allocs`].
+pub fn report(name: &str, start: Instant, before: usize){
+    let elapsed = start.elapsed();
+    println!(
+        "{:<8} {:>10.1} us/step {:>10} allocs/step",
+        name,
+        elapsed.as_secs_f64() * 1e6 / STEPS as f64,
+        (allocs() - before) / STEPS,
+    );
+}
+
+/// Deterministic weights (`OUTPUTS` rows of `INPUTS`) and inputs, made into
+/// leaves by `leaf`.
+pub fn init<V>(mut leaf: impl FnMut(f64) -> V) -> (Vec<V>, Vec<V>){
+    let w = (0..INPUTS * OUTPUTS).map(|i| leaf((i % 7) as f64 * 0.01)).collect();
+    let x = (0..INPUTS).map(|i| leaf(i as f64 * 0.1)).collect();
+    return (w, x);
+}
+
+/// The sum of `tanh(w x)` over the layer's outputs.
+pub fn dense(w: &[Var], x: &[Var]) -> Var{
+    let mut out = new_var(0.0);
+    for o in 0..OUTPUTS {
+        let mut sum = new_var(0.0);
+        for i in 0..INPUTS {
+            sum = sum.add(&w[o * INPUTS + i].mul(&x[i]));
+        }
+        out = out.add(&sum.tanh());
+    }
+    return out;
+}
@@ -0,0 +1,30 @@
+// Evaluates a small dense layer repeatedly, first recording the graph as in
+// training and then inside `no_grad` as in inference. Run with
+// `cargo bench --bench no_grad`.
+
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use std::time::Instant;
+
+use common::{allocs, dense, init, report, STEPS};
+use micrograd::{new_var, no_grad};
+
+fn bench(name: &str, eval: impl Fn() -> f64){
+    let before = allocs();
+    let start = Instant::now();
+    let mut total = 0.0;
+    for _ in 0..STEPS {
+        total += eval();
+    }
+    report(name, start, before);
+    // keep the result observable so the work is not optimized away
+    assert!(total.is_finite());
+}
+
+fn main(){
+    let (w, x) = init(new_var);
+    bench("record", || dense(&w, &x).value());
+    bench("no_grad", || no_grad(|| dense(&w, &x).value()));
+}
@@ -0,0 +1,50 @@
+// Compares the Rc-based `Var` graph with `Tape` on the same workload: a
+// forward pass over a small dense layer followed by backward, repeated as in a
+// training loop. Run with `cargo bench --bench tape`.
+
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use std::time::Instant;
+
+use common::{allocs, dense, init, report, INPUTS, OUTPUTS, STEPS};
+use micrograd::{new_var, Tape};
+
+fn bench_var(){
+    let (w, x) = init(new_var);
+    let before = allocs();
+    let start = Instant::now();
+    for _ in 0..STEPS {
+        dense(&w, &x).backward();
+    }
+    report("var", start, before);
+}
+
+fn bench_tape(){
+    let mut t = Tape::new();
+    let (w, x) = init(|v| t.var(v));
+    let zero = t.var(0.0);
+    let before = allocs();
+    let start = Instant::now();
+    for _ in 0..STEPS {
+        t.clear();
+        let mut loss = zero;
+        for o in 0..OUTPUTS {
+            let mut sum = zero;
+            for i in 0..INPUTS {
+                let p = t.mul(w[o * INPUTS + i], x[i]);
+                sum = t.add(sum, p);
+            }
+            let act = t.apply(&micrograd::ops::TanhOp, &[sum]);
+            loss = t.add(loss, act);
+        }
+        t.backward(loss);
+    }
+    report("tape", start, before);
+}
+
+fn main(){
+    bench_var();
+    bench_tape();
+}
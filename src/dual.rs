@@ -0,0 +1,142 @@
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::{Float, Operation};
+
+/// A dual number for forward-mode differentiation: a value together with its
+/// derivative (tangent) along one input direction.
+///
+/// Every op pushes the tangent through the same [`Operation`] derivatives that
+/// [`Var::backward`](crate::Var::backward) uses, so the two modes agree by
+/// construction. One forward evaluation yields the derivatives of all outputs
+/// with respect to the seeded input, which suits functions with few inputs and
+/// many outputs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dual<T: Float = f64>{
+    pub value: T,
+    pub tangent: T,
+}
+
+impl<T: Float> Dual<T>{
+    pub fn new(value: T, tangent: T) -> Dual<T>{
+        return Dual{ value, tangent };
+    }
+
+    /// The input being differentiated with respect to: tangent 1.
+    pub fn variable(value: T) -> Dual<T>{
+        return Dual{ value, tangent: T::one() };
+    }
+
+    /// A value that does not depend on the input: tangent 0.
+    pub fn constant(value: T) -> Dual<T>{
+        return Dual{ value, tangent: T::zero() };
+    }
+
+    /// Applies `op` to `inputs`, the forward-mode counterpart of
+    /// [`Var::apply`](crate::Var::apply).
+    pub fn apply(op: &impl Operation<T>, inputs: &[Dual<T>]) -> Dual<T>{
+        let x: Vec<T> = inputs.iter().map(|d| d.value).collect();
+        let value = op.op(&x);
+        let mut tangent = T::zero();
+        for (i, d) in inputs.iter().enumerate() {
+            tangent += op.grad(&x, value, i) * d.tangent;
+        }
+        return Dual{ value, tangent };
+    }
+
+    fn unary(&self, op: impl Operation<T>) -> Dual<T>{
+        return Dual::apply(&op, &[*self]);
+    }
+
+    fn binary(&self, o: &Dual<T>, op: impl Operation<T>) -> Dual<T>{
+        return Dual::apply(&op, &[*self, *o]);
+    }
+
+    op_methods!();
+}
+
+impl<T: Float> From<T> for Dual<T>{
+    fn from(value: T) -> Dual<T> { return Dual::constant(value); }
+}
+
+macro_rules! impl_binary {
+    ($trait:ident, $method:ident) => {
+        impl<T: Float> $trait for Dual<T>{
+            type Output = Dual<T>;
+            fn $method(self, o: Dual<T>) -> Dual<T> { return Dual::$method(&self, &o); }
+        }
+
+        impl<T: Float> $trait<T> for Dual<T>{
+            type Output = Dual<T>;
+            fn $method(self, o: T) -> Dual<T> { return Dual::$method(&self, &Dual::constant(o)); }
+        }
+
+        impl_binary!(@left $trait, $method, f64);
+        impl_binary!(@left $trait, $method, f32);
+    };
+    (@left $trait:ident, $method:ident, $t:ty) => {
+        impl $trait<Dual<$t>> for $t{
+            type Output = Dual<$t>;
+            fn $method(self, o: Dual<$t>) -> Dual<$t> { return Dual::$method(&Dual::constant(self), &o); }
+        }
+    };
+}
+
+impl_binary!(Add, add);
+impl_binary!(Sub, sub);
+impl_binary!(Mul, mul);
+impl_binary!(Div, div);
+
+impl<T: Float> Neg for Dual<T>{
+    type Output = Dual<T>;
+    fn neg(self) -> Dual<T> { return Dual::neg(&self); }
+}
+
+#[test]
+fn test_dual_basic() {
+    // f(x) = x^3 - 2x, f'(2) = 10
+    let x = Dual::variable(2.0f64);
+    let y = x.pow(3.0) - 2.0 * x;
+    assert_eq!(y.value, 4.0);
+    assert_eq!(y.tangent, 10.0);
+
+    let c = Dual::constant(5.0);
+    assert_eq!((c * c).tangent, 0.0);
+    assert_eq!((x / c).tangent, 0.2);
+}
+
+#[test]
+fn test_dual_matches_reverse() {
+    use crate::new_var;
+
+    // many outputs of one input: a single forward pass gives every derivative
+    let f = |x: Dual| vec![x.sin() * x.exp(), x.tanh().ln(), x.sqrt().atan(), x.gelu() / x.softplus()];
+    let outs = f(Dual::variable(0.8));
+
+    let vx = new_var(0.8);
+    let vouts = vec![vx.sin().mul(&vx.exp()), vx.tanh().ln(), vx.sqrt().atan(), vx.gelu().div(&vx.softplus())];
+    for (d, v) in outs.iter().zip(&vouts) {
+        vx.zero_grad();
+        v.backward();
+        assert_eq!(d.value, v.value());
+        assert!((d.tangent - vx.grad.get()).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn test_dual_apply_partial() {
+    // partial derivative of a two-input op along the second input
+    let a = Dual::constant(3.0);
+    let b = Dual::variable(4.0);
+    let hyp = crate::new_op(|x: &[f64]| (x[0] * x[0] + x[1] * x[1]).sqrt(), |x, out, i| x[i] / out);
+    let h = Dual::apply(&hyp, &[a, b]);
+    assert_eq!(h.value, 5.0);
+    assert_eq!(h.tangent, 0.8);
+}
+
+#[test]
+fn test_dual_f32() {
+    let x = Dual::variable(0.5f32);
+    let y = x.exp() * x.sin() + 1.0f32;
+    let expected = 0.5f32.exp() * (0.5f32.sin() + 0.5f32.cos());
+    assert!((y.tangent - expected).abs() < 1e-6);
+}
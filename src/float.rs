@@ -0,0 +1,72 @@
+use std::fmt::Debug;
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub};
+
+/// The scalar type a graph computes in. Implemented for `f32` and `f64`; a
+/// user type can take part by implementing it too.
+///
+/// Hyperparameters such as a `pow` exponent or a `leaky_relu` slope stay `f64`
+/// throughout the crate and are converted with [`Float::from_f64`].
+pub trait Float:
+    Copy
+    + Debug
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+    + 'static
+{
+    fn from_f64(v: f64) -> Self;
+    fn to_f64(self) -> f64;
+
+    fn zero() -> Self { return Self::from_f64(0.0); }
+    fn one() -> Self { return Self::from_f64(1.0); }
+
+    fn abs(self) -> Self;
+    fn max(self, o: Self) -> Self;
+    fn powf(self, p: Self) -> Self;
+    fn sqrt(self) -> Self;
+    fn exp(self) -> Self;
+    fn exp_m1(self) -> Self;
+    fn ln(self) -> Self;
+    fn ln_1p(self) -> Self;
+    fn log(self, base: Self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    fn tan(self) -> Self;
+    fn asin(self) -> Self;
+    fn acos(self) -> Self;
+    fn atan(self) -> Self;
+    fn tanh(self) -> Self;
+}
+
+macro_rules! impl_float {
+    ($t:ty) => {
+        impl Float for $t{
+            fn from_f64(v: f64) -> $t { return v as $t; }
+            fn to_f64(self) -> f64 { return self as f64; }
+
+            fn abs(self) -> $t { return <$t>::abs(self); }
+            fn max(self, o: $t) -> $t { return <$t>::max(self, o); }
+            fn powf(self, p: $t) -> $t { return <$t>::powf(self, p); }
+            fn sqrt(self) -> $t { return <$t>::sqrt(self); }
+            fn exp(self) -> $t { return <$t>::exp(self); }
+            fn exp_m1(self) -> $t { return <$t>::exp_m1(self); }
+            fn ln(self) -> $t { return <$t>::ln(self); }
+            fn ln_1p(self) -> $t { return <$t>::ln_1p(self); }
+            fn log(self, base: $t) -> $t { return <$t>::log(self, base); }
+            fn sin(self) -> $t { return <$t>::sin(self); }
+            fn cos(self) -> $t { return <$t>::cos(self); }
+            fn tan(self) -> $t { return <$t>::tan(self); }
+            fn asin(self) -> $t { return <$t>::asin(self); }
+            fn acos(self) -> $t { return <$t>::acos(self); }
+            fn atan(self) -> $t { return <$t>::atan(self); }
+            fn tanh(self) -> $t { return <$t>::tanh(self); }
+        }
+    };
+}
+
+impl_float!(f32);
+impl_float!(f64);
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::{grad, new_var, topo, Float, Node, Var};
+
+/// Forward-mode sweep over an already-built graph: the directional derivatives
+/// of `outputs` when `inputs` move along `tangents`. This is the graph-level
+/// counterpart of [`Dual`](crate::Dual) and, like it, uses only
+/// [`Operation::grad`](crate::Operation::grad).
+///
+/// `inputs` may include interior nodes: each input is treated as an
+/// independent variable, so its tangent is the one given rather than the one
+/// flowing in from its own children.
+pub fn jvp<T: Float>(outputs: &[&Var<T>], inputs: &[&Var<T>], tangents: &[T]) -> Vec<T>{
+    let order = topo(outputs);
+    let index: HashMap<*const Node<T>, usize> = order.iter().enumerate().map(|(k, v)| (Rc::as_ptr(&v.0), k)).collect();
+    let mut tangent = vec![T::zero(); order.len()];
+    let mut seeded = vec![false; order.len()];
+    for (x, &t) in inputs.iter().zip(tangents) {
+        if let Some(&k) = index.get(&Rc::as_ptr(&x.0)) {
+            tangent[k] += t;
+            seeded[k] = true;
+        }
+    }
+    for (k, v) in order.iter().enumerate() {
+        if v.children.is_empty() || seeded[k] { continue; }
+        let x: Vec<T> = v.children.iter().map(|c| c.value()).collect();
+        let mut t = T::zero();
+        for (i, c) in v.children.iter().enumerate() {
+            t += v.operation.grad(&x, v.value(), i) * tangent[index[&Rc::as_ptr(&c.0)]];
+        }
+        tangent[k] = t;
+    }
+    return outputs.iter().map(|y| tangent[index[&Rc::as_ptr(&y.0)]]).collect();
+}
+
+/// Dense Jacobian of `f` at `inputs`: row `i`, column `j` holds the derivative
+/// of output `i` with respect to input `j`.
+///
+/// Wide functions (no more outputs than inputs) use reverse mode, one backward
+/// pass per output; tall ones use [`jvp`], one forward sweep per input.
+///
+/// Both paths use only first derivatives of the ops in `f`, so they are exact
+/// for any op. If `f` itself returns gradients built with [`grad`], e.g. to
+/// take the Jacobian of a gradient, those are only as exact as each op's
+/// `grad_graph`: ops made with [`new_op`](crate::new_op), and custom ops that
+/// do not override it, drop their second-order terms, as in [`hessian`].
+pub fn jacobian<T: Float>(f: impl Fn(&[Var<T>]) -> Vec<Var<T>>, inputs: &[T]) -> Vec<Vec<T>>{
+    let xs: Vec<Var<T>> = inputs.iter().map(|&v| new_var(v)).collect();
+    let ys = f(&xs);
+    let mut jac = vec![vec![T::zero(); xs.len()]; ys.len()];
+    if ys.len() <= xs.len() {
+        for (row, y) in jac.iter_mut().zip(&ys) {
+            for x in &xs { x.grad.set(T::zero()); }
+            y.backward();
+            for (entry, x) in row.iter_mut().zip(&xs) { *entry = x.grad.get(); }
+        }
+    } else {
+        let xrefs: Vec<&Var<T>> = xs.iter().collect();
+        let yrefs: Vec<&Var<T>> = ys.iter().collect();
+        for j in 0..xs.len() {
+            let mut seed = vec![T::zero(); xs.len()];
+            seed[j] = T::one();
+            for (row, d) in jac.iter_mut().zip(jvp(&yrefs, &xrefs, &seed)) { row[j] = d; }
+        }
+    }
+    return jac;
+}
+
+/// Dense Hessian of the scalar function `f` at `inputs`, computed
+/// forward-over-reverse: the gradient is built as a graph with [`grad`], then
+/// each column is a forward sweep ([`jvp`]) through that gradient graph.
+///
+/// The result is only as exact as each op's
+/// [`Operation::grad_graph`](crate::Operation::grad_graph): ops made with
+/// [`new_op`](crate::new_op), and custom ops that do not override it, treat
+/// their first derivative as a constant, so their own second-order terms are
+/// missing from the Hessian. Check such functions with a finite-difference
+/// Hessian before relying on them, e.g. for Newton steps.
+pub fn hessian<T: Float>(f: impl Fn(&[Var<T>]) -> Var<T>, inputs: &[T]) -> Vec<Vec<T>>{
+    let xs: Vec<Var<T>> = inputs.iter().map(|&v| new_var(v)).collect();
+    let xrefs: Vec<&Var<T>> = xs.iter().collect();
+    let y = f(&xs);
+    let g = grad(&y, &xrefs);
+    let grefs: Vec<&Var<T>> = g.iter().collect();
+    let mut hess = vec![vec![T::zero(); xs.len()]; xs.len()];
+    for j in 0..xs.len() {
+        let mut seed = vec![T::zero(); xs.len()];
+        seed[j] = T::one();
+        for (row, d) in hess.iter_mut().zip(jvp(&grefs, &xrefs, &seed)) { row[j] = d; }
+    }
+    return hess;
+}
+
+#[cfg(test)]
+fn assert_close(a: &[Vec<f64>], b: &[Vec<f64>]) {
+    assert_eq!(a.len(), b.len());
+    for (ra, rb) in a.iter().zip(b) {
+        assert_eq!(ra.len(), rb.len());
+        for (x, y) in ra.iter().zip(rb) {
+            assert!((x - y).abs() < 1e-10, "{:?} vs {:?}", a, b);
+        }
+    }
+}
+
+#[test]
+fn test_jacobian_wide() {
+    // f(x, y, z) = (x y z, x^2 + sin z)
+    let f = |v: &[Var]| vec![&v[0] * &v[1] * &v[2], &v[0] * &v[0] + v[2].sin()];
+    let (x, y, z) = (1.5, -2.0, 0.5f64);
+    let jac = jacobian(f, &[x, y, z]);
+    assert_close(&jac, &[vec![y * z, x * z, x * y], vec![2.0 * x, 0.0, z.cos()]]);
+}
+
+#[test]
+fn test_jacobian_tall() {
+    // f(t) = (cos t, sin t, t^2) uses the forward sweep
+    let f = |v: &[Var]| vec![v[0].cos(), v[0].sin(), &v[0] * &v[0]];
+    let t = 0.3f64;
+    let jac = jacobian(f, &[t]);
+    assert_close(&jac, &[vec![-t.sin()], vec![t.cos()], vec![2.0 * t]]);
+
+    // and agrees with the reverse-mode path on a square function
+    let g = |v: &[Var]| vec![v[0].exp() * &v[1], &v[0] / &v[1], v[1].tanh()];
+    let rows = |v: &[Var]| g(v).into_iter().take(2).collect::<Vec<Var>>();
+    let full = jacobian(g, &[0.2, 1.7]);
+    let wide = jacobian(rows, &[0.2, 1.7]);
+    assert_close(&full[..2], &wide);
+}
+
+#[test]
+fn test_jvp_matches_dual() {
+    use crate::Dual;
+
+    let x = new_var(0.4);
+    let y = new_var(1.1);
+    let out = (&x * &y).sin() + x.exp() / &y;
+    let t = jvp(&[&out], &[&x, &y], &[0.5, -2.0]);
+
+    let dx = Dual::new(0.4, 0.5);
+    let dy = Dual::new(1.1, -2.0);
+    let dout = (dx * dy).sin() + dx.exp() / dy;
+    assert!((t[0] - dout.tangent).abs() < 1e-12);
+}
+
+#[test]
+fn test_hessian() {
+    // Rosenbrock: f = (1 - x)^2 + 100 (y - x^2)^2
+    let f = |v: &[Var]| {
+        let a = 1.0 - &v[0];
+        let b = &v[1] - &v[0] * &v[0];
+        return &a * &a + &b * &b * 100.0;
+    };
+    let (x, y) = (0.5, 1.5f64);
+    let hess = hessian(f, &[x, y]);
+    let expected = vec![
+        vec![2.0 - 400.0 * (y - x * x) + 800.0 * x * x, -400.0 * x],
+        vec![-400.0 * x, 200.0],
+    ];
+    assert_close(&hess, &expected);
+
+    // one Newton step on a quadratic lands on the minimum
+    let q = |v: &[Var]| (&v[0] - 3.0) * (&v[0] - 3.0) * 2.0 + (&v[1] + 1.0) * (&v[1] + 1.0);
+    let h = hessian(q, &[0.0, 0.0]);
+    let g = jacobian(|v: &[Var]| vec![q(v)], &[0.0, 0.0]);
+    let step = [g[0][0] / h[0][0], g[0][1] / h[1][1]];
+    assert_eq!([0.0 - step[0], 0.0 - step[1]], [3.0, -1.0]);
+}
+
+#[test]
+fn test_jvp_interior_input() {
+    // h = 3x is an independent variable here, so x's own tangent does not reach y
+    let x = new_var(2.0);
+    let h = &x * 3.0;
+    let y = h.sin();
+    assert_eq!(jvp(&[&y], &[&x, &h], &[0.0, 1.0]), vec![6f64.cos()]);
+    assert_eq!(jvp(&[&y], &[&x, &h], &[1.0, 0.0]), vec![0.0]);
+    assert_eq!(jvp(&[&(&h * &h)], &[&h], &[1.0]), vec![12.0]);
+}
+
+#[test]
+fn test_hessian_closure_op() {
+    use crate::new_op;
+
+    // x^3 as a closure op has no graph-building gradient, so its curvature
+    // 6x is lost; written with Var ops it is exact
+    let cube = || new_op(|x: &[f64]| x[0] * x[0] * x[0], |x, _, _| 3.0 * x[0] * x[0]);
+    assert_eq!(hessian(|v: &[Var]| Var::apply(cube(), &[&v[0]]), &[2.0]), vec![vec![0.0]]);
+    assert_eq!(hessian(|v: &[Var]| &v[0] * &v[0] * &v[0], &[2.0]), vec![vec![12.0]]);
+
+    // first derivatives through it are exact on both Jacobian paths
+    let f = |v: &[Var]| vec![Var::apply(cube(), &[&v[0]]), v[0].exp()];
+    assert_close(&jacobian(f, &[2.0]), &[vec![12.0], vec![2f64.exp()]]);
+}
@@ -0,0 +1,98 @@
+use crate::{new_var, Float, Var};
+
+/// Backward and finite-difference derivatives of one input, see [`gradcheck`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputCheck<T: Float = f64>{
+    pub analytic: T,
+    pub numeric: T,
+    pub abs_err: T,
+    /// `abs_err` relative to the larger of the two magnitudes (0 when both are 0).
+    pub rel_err: T,
+}
+
+/// Result of [`gradcheck`]: one entry per input, in order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GradCheck<T: Float = f64>{
+    pub inputs: Vec<InputCheck<T>>,
+    pub tol: T,
+}
+
+impl<T: Float> GradCheck<T>{
+    /// True when every input has an absolute or a relative error within `tol`.
+    pub fn passed(&self) -> bool{
+        return self.failures().next().is_none();
+    }
+
+    /// Indices of the inputs that fail the check.
+    pub fn failures(&self) -> impl Iterator<Item = usize> + '_{
+        return self.inputs.iter().enumerate()
+            .filter(|(_, c)| !(c.abs_err <= self.tol || c.rel_err <= self.tol))
+            .map(|(i, _)| i);
+    }
+}
+
+/// Compares the gradients [`Var::backward`] computes for the scalar function
+/// `f` at `inputs` against central finite differences with step `eps`.
+///
+/// `f` is called once on leaves holding `inputs` for the backward pass, then
+/// twice per input with that input shifted by `±eps`. Works for the built-in
+/// ops and for custom ones built with [`Var::apply`] alike.
+pub fn gradcheck<T: Float>(f: impl Fn(&[Var<T>]) -> Var<T>, inputs: &[T], eps: T, tol: T) -> GradCheck<T>{
+    let xs: Vec<Var<T>> = inputs.iter().map(|&v| new_var(v)).collect();
+    f(&xs).backward();
+    let eval = |j: usize, delta: T| {
+        let shifted: Vec<Var<T>> = inputs.iter().enumerate()
+            .map(|(k, &v)| new_var(if k == j { v + delta } else { v }))
+            .collect();
+        return f(&shifted).value();
+    };
+    let checks = xs.iter().enumerate().map(|(j, x)| {
+        let analytic = x.grad.get();
+        let numeric = (eval(j, eps) - eval(j, -eps)) / (eps + eps);
+        let abs_err = (analytic - numeric).abs();
+        let scale = analytic.abs().max(numeric.abs());
+        let rel_err = if scale == T::zero() { T::zero() } else { abs_err / scale };
+        return InputCheck{ analytic, numeric, abs_err, rel_err };
+    }).collect();
+    return GradCheck{ inputs: checks, tol };
+}
+
+#[test]
+fn test_gradcheck_builtin_ops() {
+    let f = |v: &[Var]| {
+        let a = (&v[0] * &v[1]).tanh() + v[2].exp().gelu();
+        return a / (v[1].sigmoid() + 1.0) - v[0].sqrt() * v[2].atan();
+    };
+    let report = gradcheck(f, &[0.7, -0.4, 1.3], 1e-6, 1e-6);
+    assert!(report.passed(), "{:?}", report);
+    assert_eq!(report.inputs.len(), 3);
+    assert!(report.inputs.iter().all(|c| c.abs_err < 1e-8));
+}
+
+#[test]
+fn test_gradcheck_custom_op() {
+    use crate::new_op;
+
+    // x^2 y with a correct and a deliberately wrong partial for y
+    let good = |v: &[Var]| Var::apply(new_op(|x| x[0] * x[0] * x[1], |x, _, i| {
+        if i == 0 { 2.0 * x[0] * x[1] } else { x[0] * x[0] }
+    }), &[&v[0], &v[1]]);
+    let bad = |v: &[Var]| Var::apply(new_op(|x| x[0] * x[0] * x[1], |x, _, i| {
+        if i == 0 { 2.0 * x[0] * x[1] } else { x[0] }
+    }), &[&v[0], &v[1]]);
+
+    assert!(gradcheck(good, &[3.0, 2.0], 1e-6, 1e-6).passed());
+    let report = gradcheck(bad, &[3.0, 2.0], 1e-6, 1e-6);
+    assert!(!report.passed());
+    assert_eq!(report.failures().collect::<Vec<_>>(), vec![1]);
+    assert!((report.inputs[1].analytic - 3.0).abs() < 1e-12);
+    assert!((report.inputs[1].numeric - 9.0).abs() < 1e-6);
+    assert!((report.inputs[1].rel_err - 2.0 / 3.0).abs() < 1e-6);
+}
+
+#[test]
+fn test_gradcheck_f32() {
+    let f = |v: &[Var<f32>]| (&v[0] * &v[1]).sin() + v[0].exp();
+    let report = gradcheck(f, &[0.3f32, 0.8], 1e-2, 1e-3);
+    assert!(report.passed(), "{:?}", report);
+}
@@ -1,131 +1,369 @@
-use std::cell::Cell;
+#![allow(clippy::needless_return)]
 
-pub struct Var<'a>{
-    pub value: f64,
-    pub grad: Cell<f64>,
-    visited: Cell<bool>,
-    pub ch1: Option<&'a Var<'a>>,
-    pub ch2: Option<&'a Var<'a>>,
-    operation: Box<dyn Operation + 'a>,
-}
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::rc::Rc;
+
+mod float;
+#[macro_use]
+pub mod ops;
+pub mod dual;
+pub mod functional;
+pub mod gradcheck;
+pub mod nn;
+mod no_grad;
+pub mod optim;
+mod overload;
+pub mod scheduler;
+pub mod state;
+pub mod tape;
+
+pub use dual::Dual;
+pub use float::Float;
+pub use functional::{hessian, jacobian, jvp};
+pub use gradcheck::{gradcheck, GradCheck, InputCheck};
+pub use no_grad::{is_grad_enabled, no_grad};
+pub use ops::{new_op, Operation};
+pub use optim::{Adadelta, Adagrad, Adam, Optimizer, RMSProp, SGD};
+pub use scheduler::{
+    CosineAnnealingWarmRestarts, ExponentialLR, LinearWarmup, OneCycle, PlateauMode, ReduceOnPlateau, Scheduler, Sequential, StepLR,
+};
+pub use state::{StateDict, StateError};
+pub use tape::{Tape, TapeVar};
+use ops::NoOP;
 
-trait Operation{
-    fn op(&self, a: f64, b: f64) -> f64;
-    fn grad(&self, a: f64, b: f64) -> f64;
+/// How [`Var::backward_mode`] combines new gradients with stored ones.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GradMode{
+    /// Add to the stored gradients, so several backward calls sum up; use
+    /// [`Var::zero_grad`] to start over.
+    #[default]
+    Accumulate,
+    /// Replace the stored gradients of every node reached by the pass.
+    Overwrite,
 }
 
-struct NoOP;
-struct AddOP;
-struct NegOp;
-struct MulOp;
-struct PowOp{
-    p: f64,
+/// A node in the computation graph. `Var` is a cheap, reference-counted handle:
+/// cloning it shares the node, and every node keeps its children alive, so
+/// graphs can be built in loops, returned from functions and stored in structs.
+///
+/// Values and gradients are of type `T`, `f64` unless stated otherwise.
+pub struct Var<T: Float = f64>(Rc<Node<T>>);
+
+pub struct Node<T: Float = f64>{
+    value: Cell<T>,
+    pub grad: Cell<T>,
+    visited: Cell<bool>,
+    requires_grad: Cell<bool>,
+    hooks: RefCell<Vec<Hook<T>>>,
+    // set by clear_hooks, so that run_hooks can tell the hooks it is running
+    // were cleared meanwhile
+    hooks_cleared: Cell<bool>,
+    children: Vec<Var<T>>,
+    operation: Box<dyn Operation<T>>,
 }
 
-impl Operation for NoOP{
-    fn op(&self, _: f64, _: f64) -> f64 { return 0.0 }
-    fn grad(&self, _: f64, _: f64) -> f64 { return 0.0 }
+type Hook<T> = Box<dyn FnMut(T) -> T>;
+
+// derived Clone would require T: Clone on the handle for no reason
+impl<T: Float> Clone for Var<T>{
+    fn clone(&self) -> Var<T> { return Var(self.0.clone()); }
 }
 
-impl Operation for AddOP{
-    fn op(&self, a: f64, b: f64) -> f64 { return a + b }
-    fn grad(&self, _: f64, _: f64) -> f64 { return 1.0; }
+impl<T: Float> Deref for Var<T>{
+    type Target = Node<T>;
+    fn deref(&self) -> &Node<T> { return &self.0; }
 }
 
-impl Operation for NegOp{
-    fn op(&self, a: f64, _: f64) -> f64 { return -a; }
-    fn grad(&self, _: f64, _: f64) -> f64 { return -1.0; }
+// The default drop glue would recurse once per link of a chain; unlink children
+// onto an explicit stack instead so that dropping a deep graph cannot overflow.
+impl<T: Float> Drop for Node<T>{
+    fn drop(&mut self){
+        let mut stack = std::mem::take(&mut self.children);
+        while let Some(v) = stack.pop() {
+            if let Ok(mut node) = Rc::try_unwrap(v.0) {
+                stack.append(&mut node.children);
+            }
+        }
+    }
 }
 
-impl Operation for MulOp{
-    fn op(&self, a: f64, b: f64) -> f64 { return a * b; }
-    fn grad(&self, _: f64, b: f64) -> f64 { return b; }
+pub fn new_var<T: Float>(value: T) -> Var<T>{
+    return leaf(value, true);
 }
 
-impl Operation for PowOp{
-    fn op(&self, a: f64, _: f64) -> f64 { return a.powf(self.p); }
-    fn grad(&self, a: f64, _: f64) -> f64 { return self.p * a.powf(self.p - 1.0); }
+/// A leaf that never requires a gradient: `backward` neither writes its `grad`
+/// nor spends any work on the parts of a graph that only depend on constants.
+pub fn new_const<T: Float>(value: T) -> Var<T>{
+    return leaf(value, false);
 }
 
-pub fn new_var<'a>(value: f64) -> Var<'a>{
-    return Var{
-        value,
-        grad: Cell::new(0.0),
+fn leaf<T: Float>(value: T, requires_grad: bool) -> Var<T>{
+    return Var(Rc::new(Node{
+        value: Cell::new(value),
+        grad: Cell::new(T::zero()),
         visited: Cell::new(false),
-        ch1: None,
-        ch2: None,
+        requires_grad: Cell::new(requires_grad),
+        hooks: RefCell::new(Vec::new()),
+        hooks_cleared: Cell::new(false),
+        children: Vec::new(),
         operation: Box::new(NoOP)
-    }
+    }));
 }
 
-fn combine<'a>(x: &'a Var<'a>, y: &'a Var<'a>, op: impl Operation + 'a) -> Var<'a>{
-    return Var{
-        value: op.op(x.value, y.value),
-        grad: Cell::new(0.0),
+fn combine<T: Float>(op: impl Operation<T> + 'static, inputs: &[&Var<T>]) -> Var<T>{
+    // most ops take one or two inputs: gather their values without allocating
+    let mut small = [T::zero(); 2];
+    let large: Vec<T>;
+    let x: &[T] = if inputs.len() <= small.len() {
+        for (s, c) in small.iter_mut().zip(inputs) { *s = c.value(); }
+        &small[..inputs.len()]
+    } else {
+        large = inputs.iter().map(|c| c.value()).collect();
+        &large
+    };
+    if !is_grad_enabled() {
+        return new_const(op.op(x));
+    }
+    return Var(Rc::new(Node{
+        value: Cell::new(op.op(x)),
+        grad: Cell::new(T::zero()),
         visited: Cell::new(false),
-        ch1: Some(x),
-        ch2: Some(y),
+        requires_grad: Cell::new(inputs.iter().any(|c| c.requires_grad.get())),
+        hooks: RefCell::new(Vec::new()),
+        hooks_cleared: Cell::new(false),
+        children: inputs.iter().map(|&c| c.clone()).collect(),
         operation: Box::new(op),
+    }));
+}
+
+// Depth-first post-order over the graphs below `roots`: every child appears
+// before its parent and each node appears exactly once, however many times it is
+// reused. Uses an explicit stack so that arbitrarily deep graphs do not overflow
+// the call stack; every graph walk should go through here.
+fn topo<'v, T: Float>(roots: &[&'v Var<T>]) -> Vec<&'v Var<T>>{
+    return topo_by(roots, |_| true);
+}
+
+// `topo` restricted to the nodes for which `follow` holds; the walk does not
+// descend below a node that is left out.
+fn topo_by<'v, T: Float>(roots: &[&'v Var<T>], follow: impl Fn(&Var<T>) -> bool) -> Vec<&'v Var<T>>{
+    let mut order = Vec::new();
+    let mut stack: Vec<(&Var<T>, bool)> = roots.iter().rev().filter(|r| follow(r)).map(|&r| (r, false)).collect();
+    while let Some((v, expanded)) = stack.pop() {
+        if expanded { order.push(v); continue; }
+        if v.visited.get() { continue; }
+        v.visited.set(true);
+        stack.push((v, true));
+        for c in v.children.iter() {
+            if !c.visited.get() && follow(c) { stack.push((c, false)); }
+        }
     }
+    for v in order.iter() { v.visited.set(false); }
+    return order;
 }
 
-impl<'a> Var<'a> {
-    pub fn add(&'a self, o: &'a Var<'a>) -> Var<'a> {
-        return combine(self, o, AddOP{});
+/// Backpropagates from several outputs at once, seeding each with its own
+/// gradient: the leaves receive the vector-Jacobian product of the seeds with
+/// the outputs. Nodes shared between outputs are visited once, and the result
+/// is the same as calling `backward_with` on the seed-weighted sum of the
+/// outputs, without building that sum.
+///
+/// Only nodes that require a gradient are visited; see [`Var::requires_grad`].
+/// Hooks run as described on [`Var::register_hook`].
+pub fn backward_many<T: Float>(outputs: &[(&Var<T>, T)], mode: GradMode){
+    let roots: Vec<&Var<T>> = outputs.iter().map(|&(v, _)| v).collect();
+    let order = topo_by(&roots, |v| v.requires_grad.get());
+    // each node's grad cell holds only this pass's contribution while the
+    // pass runs, so shared nodes never propagate gradient left over from an
+    // earlier call
+    let prev: Vec<T> = order.iter().map(|v| v.grad.replace(T::zero())).collect();
+    for &(v, seed) in outputs {
+        if v.requires_grad.get() { v.grad.set(v.grad.get() + seed); }
+    }
+    // reverse topological order: a node's grad is complete before it is pushed on
+    for v in order.iter().rev() {
+        v.run_hooks();
+        v._backward();
+    }
+    if mode == GradMode::Accumulate {
+        for (v, p) in order.iter().zip(prev) { v.grad.set(v.grad.get() + p); }
     }
+}
 
-    pub fn neg(&'a self) -> Var<'a> {
-        return Var{
-            value: NegOp{}.op(self.value, 0.0),
-            grad: Cell::new(0.0),
-            visited: Cell::new(false),
-            ch1: Some(self),
-            ch2: None,
-            operation: Box::new(NegOp{}),
+/// Gradients of `output` with respect to each of `inputs`, returned as graph
+/// nodes rather than numbers: the backward pass is itself recorded out of
+/// `Var` ops (through [`Operation::grad_graph`]), so the results can be
+/// differentiated again for second derivatives, Hessian-vector products or
+/// gradient penalties. The `grad` cells of the graph are left untouched.
+///
+/// Second derivatives are only as exact as each op's `grad_graph`: ops made
+/// with [`new_op`], and custom ops that do not override it, contribute their
+/// first derivative as a constant and drop their own second-order terms.
+///
+/// An input that `output` does not depend on, or only depends on through nodes
+/// that do not require a gradient, gets a zero constant.
+pub fn grad<T: Float>(output: &Var<T>, inputs: &[&Var<T>]) -> Vec<Var<T>>{
+    let order = topo_by(&[output], |v| v.requires_grad.get());
+    let index: HashMap<*const Node<T>, usize> = order.iter().enumerate().map(|(k, v)| (Rc::as_ptr(&v.0), k)).collect();
+    let mut adjoint: Vec<Option<Var<T>>> = vec![None; order.len()];
+    // the output comes last in post-order
+    if let Some(last) = adjoint.last_mut() { *last = Some(new_const(T::one())); }
+    for (k, v) in order.iter().enumerate().rev() {
+        let g = match &adjoint[k] {
+            Some(g) => g.clone(),
+            None => continue,
+        };
+        for (i, c) in v.children.iter().enumerate() {
+            if !c.requires_grad.get() { continue; }
+            let contrib = v.operation.grad_graph(&v.children, v, &g, i);
+            let j = index[&Rc::as_ptr(&c.0)];
+            adjoint[j] = Some(match adjoint[j].take() {
+                Some(a) => a.add(&contrib),
+                None => contrib,
+            });
         }
     }
+    return inputs.iter().map(|x| {
+        return index.get(&Rc::as_ptr(&x.0)).and_then(|&j| adjoint[j].clone()).unwrap_or_else(|| new_const(T::zero()));
+    }).collect();
+}
 
-    pub fn mul(&'a self, o: &'a Var<'a>) -> Var<'a> {
-        return combine(self, o, MulOp{})
+impl<T: Float> Var<T> {
+    /// Applies a (possibly user-defined) operation to `inputs`, which are handed
+    /// to `op` in order.
+    pub fn apply(op: impl Operation<T> + 'static, inputs: &[&Var<T>]) -> Var<T> {
+        return combine(op, inputs);
     }
 
-    pub fn pow(&'a self, p: f64) -> Var<'a>{
-        let pow_op = PowOp{ p };
-        return Var{
-            value: pow_op.op(self.value, p),
-            grad: Cell::new(0.0),
-            visited: Cell::new(false),
-            ch1: Some(self),
-            ch2: None,
-            operation: Box::new(pow_op),
-        }
+    fn unary(&self, op: impl Operation<T> + 'static) -> Var<T>{
+        return combine(op, &[self]);
+    }
+
+    fn binary(&self, o: &Var<T>, op: impl Operation<T> + 'static) -> Var<T>{
+        return combine(op, &[self, o]);
+    }
+
+    op_methods!();
+
+    pub fn value(&self) -> T {
+        return self.value.get();
+    }
+
+    /// Overwrites the value of a leaf in place, e.g. for a parameter update.
+    /// Nodes already computed from it keep the value they were built with, so
+    /// a graph must be built again to see the change, and a backward pass
+    /// through an old one mixes the new leaf value with the old results.
+    ///
+    /// Panics if this node is not a leaf.
+    pub fn set_value(&self, value: T) {
+        assert!(self.children.is_empty(), "set_value called on a non-leaf node");
+        self.value.set(value);
     }
 
-    fn reset_vis(&self){
-        self.visited.set(false);
-        if self.ch1.is_some() { self.ch1.unwrap().reset_vis(); }
-        if self.ch2.is_some() { self.ch2.unwrap().reset_vis(); }
+    pub fn children(&self) -> &[Var<T>] {
+        return &self.children;
     }
 
+    /// Whether `backward` computes a gradient for this node. Leaves made with
+    /// [`new_var`] require one and those made with [`new_const`] or
+    /// [`Var::detach`] do not; any other node requires one if one of its
+    /// inputs did when it was created.
+    pub fn requires_grad(&self) -> bool {
+        return self.requires_grad.get();
+    }
+
+    /// Turns gradient tracking for a leaf on or off. Turning it off also stops
+    /// gradient reaching the leaf through graphs built earlier; turning it on
+    /// only affects nodes built afterwards.
+    ///
+    /// Panics if this node is not a leaf.
+    pub fn set_requires_grad(&self, requires_grad: bool) {
+        assert!(self.children.is_empty(), "set_requires_grad called on a non-leaf node");
+        self.requires_grad.set(requires_grad);
+    }
+
+    /// A constant leaf with the same value: the result is cut off from this
+    /// node's graph, so no gradient flows back through it (stop-gradient).
+    pub fn detach(&self) -> Var<T> {
+        return new_const(self.value());
+    }
+
+    fn topo(&self) -> Vec<&Var<T>>{
+        return topo(&[self]);
+    }
+
+    /// Backpropagates from this node with [`GradMode::Accumulate`].
     pub fn backward(&self){
-        self.grad.set(1.0);
-        self.reset_vis();
-        self._backward();
+        self.backward_mode(GradMode::Accumulate);
+    }
+
+    /// Like [`Var::backward`], but seeds this node with `seed` instead of 1, so
+    /// the leaves receive `seed` times the derivative.
+    pub fn backward_with(&self, seed: T){
+        backward_many(&[(self, seed)], GradMode::Accumulate);
+    }
+
+    /// Backpropagates from this node, seeding it with a gradient of 1. `mode`
+    /// decides whether the gradients computed by this pass are added to the
+    /// ones already stored on each node of the subgraph or replace them.
+    pub fn backward_mode(&self, mode: GradMode){
+        backward_many(&[(self, T::one())], mode);
     }
 
     fn _backward(&self){
-        if self.ch1.is_some(){
-            self.ch1.unwrap().grad.set(
-                self.grad.get() * self.operation.grad(self.ch1.unwrap().value, self.ch2.unwrap().value) + self.ch1.unwrap().grad.get()
-            );
+        let x: Vec<T> = self.children.iter().map(|c| c.value()).collect();
+        for (i, c) in self.children.iter().enumerate() {
+            if !c.requires_grad.get() { continue; }
+            c.grad.set(self.grad.get() * self.operation.grad(&x, self.value(), i) + c.grad.get());
         }
-        if self.ch2.is_some(){
-            self.ch2.unwrap().grad.set(
-                self.grad.get() * self.operation.grad(self.ch2.unwrap().value, self.ch1.unwrap().value) + self.ch2.unwrap().grad.get()
-            );
+    }
+
+    /// Registers `hook` to run during backward passes once this node's gradient
+    /// for the pass is final, i.e. after every node that uses it has been
+    /// processed and before anything is propagated to its children. The hook
+    /// receives that gradient and returns the one to use instead: it is stored
+    /// on the node and is what flows on to the children, so hooks can log,
+    /// clip, scale or zero the gradient below a node.
+    ///
+    /// Nodes are handled in reverse topological order, so a hook on a node runs
+    /// before the hooks on any node it was computed from; several hooks on one
+    /// node run in registration order, each seeing the previous one's result.
+    /// In [`GradMode::Accumulate`] the hook sees only the current pass's
+    /// contribution, not the gradient accumulated by earlier passes. Hooks are
+    /// not run by [`grad`], nor on nodes that do not require a gradient.
+    pub fn register_hook(&self, hook: impl FnMut(T) -> T + 'static){
+        self.hooks.borrow_mut().push(Box::new(hook));
+    }
+
+    /// Removes every hook registered on this node. A hook may call this (or
+    /// [`Var::register_hook`]) on its own node, e.g. to run only once; the
+    /// change applies from the next backward pass.
+    pub fn clear_hooks(&self){
+        self.hooks.borrow_mut().clear();
+        self.hooks_cleared.set(true);
+    }
+
+    // Nothing is borrowed while a hook runs, so hooks can change this node's
+    // hooks; ones registered meanwhile go after the existing ones.
+    fn run_hooks(&self){
+        if self.hooks.borrow().is_empty() { return; }
+        let mut hooks = std::mem::take(&mut *self.hooks.borrow_mut());
+        self.hooks_cleared.set(false);
+        for hook in hooks.iter_mut() {
+            self.grad.set(hook(self.grad.get()));
         }
-        if self.ch1.is_some() { self.ch1.unwrap()._backward(); }
-        if self.ch2.is_some() { self.ch2.unwrap()._backward(); }
+        if !self.hooks_cleared.get() {
+            let mut current = self.hooks.borrow_mut();
+            hooks.append(&mut current);
+            *current = hooks;
+        }
+    }
+
+    /// Sets the gradient of this node and every node below it to zero.
+    pub fn zero_grad(&self){
+        for v in self.topo() { v.grad.set(T::zero()); }
     }
 }
 
@@ -138,6 +376,750 @@ fn test_all() {
     let r = a.mul(&x);
     let t = r.add(&b);
     t.backward();
-    assert_eq!(x.grad.get(), a.value);
+    assert_eq!(x.grad.get(), a.value());
 }
 
+
+#[test]
+fn test_repeated_leaf() {
+    let a = new_var(3.0);
+    let sq = a.mul(&a);
+    sq.backward();
+    assert_eq!(a.grad.get(), 6.0);
+
+    let b = new_var(3.0);
+    let twice = b.add(&b);
+    twice.backward();
+    assert_eq!(b.grad.get(), 2.0);
+}
+
+#[test]
+fn test_diamond() {
+    // d = (a*a) * (a*a + a) = a^4 + a^3, d' = 4a^3 + 3a^2
+    let a = new_var(2.0);
+    let b = a.mul(&a);
+    let c = b.add(&a);
+    let d = b.mul(&c);
+    d.backward();
+    assert_eq!(d.value(), 24.0);
+    assert_eq!(b.grad.get(), 10.0);
+    assert_eq!(c.grad.get(), 4.0);
+    assert_eq!(a.grad.get(), 44.0);
+}
+
+#[test]
+fn test_deep_shared_dag() {
+    // each level doubles the previous one, so y = 2^30 * x; a traversal that
+    // does not share work would take 2^30 steps here
+    let x = new_var(1.0);
+    let mut y = x.clone();
+    for _ in 0..30 {
+        y = y.add(&y);
+    }
+    y.backward();
+    assert_eq!(y.value(), (1u64 << 30) as f64);
+    assert_eq!(x.grad.get(), (1u64 << 30) as f64);
+}
+
+#[test]
+fn test_unary_backward() {
+    // y = -(a^2) * b, dy/da = -2ab, dy/db = -a^2
+    let a = new_var(3.0);
+    let b = new_var(5.0);
+    let sq = a.pow(2.0);
+    let n = sq.neg();
+    let y = n.mul(&b);
+    y.backward();
+    assert_eq!(y.value(), -45.0);
+    assert_eq!(a.grad.get(), -30.0);
+    assert_eq!(b.grad.get(), -9.0);
+}
+
+#[test]
+fn test_sub_via_neg() {
+    let a = new_var(7.0);
+    let b = new_var(4.0);
+    let nb = b.neg();
+    let d = a.add(&nb);
+    d.backward();
+    assert_eq!(d.value(), 3.0);
+    assert_eq!(a.grad.get(), 1.0);
+    assert_eq!(b.grad.get(), -1.0);
+}
+
+#[test]
+fn test_deep_chain() {
+    // y = x + 1 + 1 + ... ; also checks that the chain drops without recursing
+    let x = new_var(0.0);
+    let one = new_var(1.0);
+    let mut y = x.clone();
+    for _ in 0..1_000_000 {
+        y = y.add(&one);
+    }
+    y.backward();
+    assert_eq!(y.value(), 1_000_000.0);
+    assert_eq!(x.grad.get(), 1.0);
+    assert_eq!(one.grad.get(), 1_000_000.0);
+}
+
+#[test]
+fn test_apply_custom() {
+    struct FusedMulAdd;
+
+    impl Operation for FusedMulAdd{
+        fn op(&self, x: &[f64]) -> f64 { return x[0].mul_add(x[1], x[2]); }
+        fn grad(&self, x: &[f64], _: f64, i: usize) -> f64 {
+            return match i { 0 => x[1], 1 => x[0], _ => 1.0 };
+        }
+    }
+
+    let a = new_var(2.0);
+    let b = new_var(5.0);
+    let c = new_var(1.0);
+    let y = Var::apply(FusedMulAdd, &[&a, &b, &c]);
+    let z = y.mul(&a);
+    z.backward();
+    assert_eq!(z.value(), 22.0);
+    assert_eq!(a.grad.get(), 2.0 * 2.0 * 5.0 + 1.0);
+    assert_eq!(b.grad.get(), 4.0);
+    assert_eq!(c.grad.get(), 2.0);
+}
+
+#[test]
+fn test_apply_closure() {
+    // log clipped from below: no gradient flows once the input is clipped
+    let clipped_ln = || new_op(|x: &[f64]| x[0].max(0.5).ln(), |x: &[f64], _, _| {
+        if x[0] > 0.5 { 1.0 / x[0] } else { 0.0 }
+    });
+    let p = new_var(4.0);
+    let lp = Var::apply(clipped_ln(), &[&p]);
+    lp.backward();
+    assert_eq!(p.grad.get(), 0.25);
+
+    let q = new_var(0.1);
+    let lq = Var::apply(clipped_ln(), &[&q]);
+    lq.backward();
+    assert_eq!(lq.value(), 0.5f64.ln());
+    assert_eq!(q.grad.get(), 0.0);
+}
+
+#[test]
+fn test_sub_div() {
+    // y = (a - b) / (a * b), dy/da = 1/a^2, dy/db = -1/b^2
+    let a = new_var(2.0);
+    let b = new_var(4.0);
+    let d = a.sub(&b);
+    let p = a.mul(&b);
+    let y = d.div(&p);
+    y.backward();
+    assert_eq!(y.value(), -0.25);
+    assert_eq!(a.grad.get(), 0.25);
+    assert_eq!(b.grad.get(), -0.0625);
+}
+
+#[test]
+fn test_div_by_zero() {
+    let a = new_var(3.0);
+    let z = new_var(0.0);
+    let y = a.div(&z);
+    y.backward();
+    assert_eq!(y.value(), f64::INFINITY);
+    assert_eq!(a.grad.get(), f64::INFINITY);
+    assert_eq!(z.grad.get(), f64::NEG_INFINITY);
+
+    let zero = new_var(0.0f64);
+    let nan = zero.div(&zero);
+    nan.backward();
+    assert!(nan.value().is_nan());
+    assert!(zero.grad.get().is_nan());
+}
+
+#[test]
+fn test_transcendental_backward() {
+    // y = exp(sin(x)) * ln(x), compared against a central difference
+    let f = |x: f64| x.sin().exp() * x.ln();
+    let x = new_var(1.3);
+    let s = x.sin();
+    let e = s.exp();
+    let l = x.ln();
+    let y = e.mul(&l);
+    y.backward();
+    let eps = 1e-6;
+    let numeric = (f(1.3 + eps) - f(1.3 - eps)) / (2.0 * eps);
+    assert_eq!(y.value(), f(1.3));
+    assert!((x.grad.get() - numeric).abs() < 1e-6);
+
+    // atan(tan(x)) and asin(sin(x)) are the identity near 0
+    let t = new_var(0.4);
+    let tt = t.tan();
+    let at = tt.atan();
+    at.backward();
+    assert!((at.value() - 0.4).abs() < 1e-12);
+    assert!((t.grad.get() - 1.0).abs() < 1e-12);
+
+    let u = new_var(0.4);
+    let su = u.sin();
+    let asu = su.asin();
+    asu.backward();
+    assert!((u.grad.get() - 1.0).abs() < 1e-12);
+}
+
+#[test]
+fn test_ln_domain() {
+    let z = new_var(0.0);
+    let lz = z.ln();
+    lz.backward();
+    assert_eq!(lz.value(), f64::NEG_INFINITY);
+    assert_eq!(z.grad.get(), f64::INFINITY);
+
+    let n = new_var(-2.0f64);
+    let ln = n.log(10.0);
+    ln.backward();
+    assert!(ln.value().is_nan());
+}
+
+#[test]
+fn test_tanh_neuron() {
+    // the two-input neuron from the original micrograd walkthrough
+    let x1 = new_var(2.0);
+    let x2 = new_var(0.0);
+    let w1 = new_var(-3.0);
+    let w2 = new_var(1.0);
+    let b = new_var(6.881373587019543);
+    let x1w1 = x1.mul(&w1);
+    let x2w2 = x2.mul(&w2);
+    let s = x1w1.add(&x2w2);
+    let n = s.add(&b);
+    let o = n.tanh();
+    o.backward();
+    assert!((o.value() - std::f64::consts::FRAC_1_SQRT_2).abs() < 1e-12);
+    assert!((x1.grad.get() + 1.5).abs() < 1e-12);
+    assert!((w1.grad.get() - 1.0).abs() < 1e-12);
+    assert!((x2.grad.get() - 0.5).abs() < 1e-12);
+    assert_eq!(w2.grad.get(), 0.0);
+}
+
+#[test]
+fn test_operators_match_methods() {
+    let a = new_var(4.0);
+    let x = new_var(3.0);
+    let b = new_var(10.0);
+    let y = &a * &x + &b * 2.0;
+    y.backward();
+    assert_eq!(y.value(), 32.0);
+
+    let a2 = new_var(4.0);
+    let x2 = new_var(3.0);
+    let b2 = new_var(10.0);
+    let two = new_var(2.0);
+    let ax = a2.mul(&x2);
+    let b2t = b2.mul(&two);
+    let y2 = ax.add(&b2t);
+    y2.backward();
+    assert_eq!(y2.value(), y.value());
+    assert_eq!(a.grad.get(), a2.grad.get());
+    assert_eq!(x.grad.get(), x2.grad.get());
+    assert_eq!(b.grad.get(), b2.grad.get());
+    assert_eq!(b.grad.get(), 2.0);
+}
+
+#[test]
+fn test_operators_scalar_mixing() {
+    // y = (1 - a) / (a * 2) + -a, dy/da = -1/(2a^2) - 1
+    let a = new_var(0.5f64);
+    let y = (1.0 - &a) / (&a * 2.0) + -&a;
+    y.backward();
+    assert_eq!(y.value(), 0.0);
+    assert_eq!(a.grad.get(), -3.0);
+
+    let b = new_var(3.0f64);
+    let z = 2.0 * &b - 1.0 + (&b / 3.0) * &b;
+    z.backward();
+    assert_eq!(z.value(), 8.0);
+    assert_eq!(b.grad.get(), 4.0);
+    assert_eq!(z.children().len(), 2);
+}
+
+#[test]
+fn test_dynamic_graph() {
+    // graphs built in loops, returned from functions and stored in structs
+    struct Affine{
+        w: Vec<Var>,
+        b: Var,
+    }
+
+    impl Affine{
+        fn forward(&self, x: &[f64]) -> Var{
+            let mut out = self.b.clone();
+            for (w, &xi) in self.w.iter().zip(x) {
+                out = out + w * xi;
+            }
+            return out;
+        }
+    }
+
+    fn dot(a: &[Var], b: &[Var]) -> Var{
+        let mut sum = new_var(0.0);
+        for (x, y) in a.iter().zip(b) {
+            sum = sum.add(&x.mul(y));
+        }
+        return sum;
+    }
+
+    let a: Vec<Var> = (1..=3).map(|i| new_var(i as f64)).collect();
+    let b: Vec<Var> = (4..=6).map(|i| new_var(i as f64)).collect();
+    let y = dot(&a, &b);
+    y.backward();
+    assert_eq!(y.value(), 32.0);
+    for (x, w) in a.iter().zip(&b) {
+        assert_eq!(x.grad.get(), w.value());
+        assert_eq!(w.grad.get(), x.value());
+    }
+
+    let model = Affine{ w: vec![new_var(2.0), new_var(-1.0)], b: new_var(0.5) };
+    let out = model.forward(&[3.0, 4.0]);
+    out.backward();
+    assert_eq!(out.value(), 2.5);
+    assert_eq!(model.w[0].grad.get(), 3.0);
+    assert_eq!(model.w[1].grad.get(), 4.0);
+    assert_eq!(model.b.grad.get(), 1.0);
+}
+
+#[test]
+fn test_accumulate_and_zero_grad() {
+    let a = new_var(3.0);
+    let b = new_var(4.0);
+    let y = a.mul(&b);
+    y.backward();
+    y.backward();
+    assert_eq!(a.grad.get(), 8.0);
+    assert_eq!(b.grad.get(), 6.0);
+    y.zero_grad();
+    assert_eq!(a.grad.get(), 0.0);
+    assert_eq!(y.grad.get(), 0.0);
+    y.backward();
+    assert_eq!(a.grad.get(), 4.0);
+}
+
+#[test]
+fn test_overwrite_mode() {
+    let a = new_var(3.0);
+    let b = new_var(4.0);
+    let y = a.mul(&b);
+    y.backward();
+    y.backward_mode(GradMode::Overwrite);
+    assert_eq!(a.grad.get(), 4.0);
+    assert_eq!(b.grad.get(), 3.0);
+
+    // only the nodes reached by the pass are overwritten
+    let c = new_var(1.0);
+    c.grad.set(5.0);
+    let z = a.add(&a);
+    z.backward_mode(GradMode::Overwrite);
+    assert_eq!(a.grad.get(), 2.0);
+    assert_eq!(c.grad.get(), 5.0);
+}
+
+#[test]
+fn test_multi_loss_accumulation() {
+    // two losses sharing an interior node give the same gradients as their sum
+    let w = new_var(0.5);
+    let x = new_var(2.0);
+    let h = (&w * &x).tanh();
+    let l1 = &h * &h;
+    let l2 = &h * 3.0 + &w;
+    l1.backward();
+    l2.backward();
+
+    let w2 = new_var(0.5);
+    let x2 = new_var(2.0);
+    let h2 = (&w2 * &x2).tanh();
+    let total = &h2 * &h2 + (&h2 * 3.0 + &w2);
+    total.backward();
+
+    assert!((w.grad.get() - w2.grad.get()).abs() < 1e-12);
+    assert!((x.grad.get() - x2.grad.get()).abs() < 1e-12);
+    assert!((h.grad.get() - h2.grad.get()).abs() < 1e-12);
+}
+
+#[test]
+fn test_backward_with_seed() {
+    let a = new_var(3.0);
+    let b = new_var(4.0);
+    let y = a.mul(&b);
+    y.backward_with(0.5);
+    assert_eq!(y.grad.get(), 0.5);
+    assert_eq!(a.grad.get(), 2.0);
+    assert_eq!(b.grad.get(), 1.5);
+}
+
+#[test]
+fn test_backward_many() {
+    let x = new_var(1.5);
+    let w = new_var(-2.0);
+    let h = (&x * &w).sin();
+    let y1 = &h * &x;
+    let y2 = h.exp();
+    // y3 depends on y1, which is also seeded directly
+    let y3 = &y1 * &y1;
+    backward_many(&[(&y1, 2.0), (&y2, -1.0), (&y3, 0.5)], GradMode::Accumulate);
+
+    let xs = new_var(1.5);
+    let ws = new_var(-2.0);
+    let hs = (&xs * &ws).sin();
+    let y1s = &hs * &xs;
+    let total = &y1s * 2.0 + hs.exp() * -1.0 + &y1s * &y1s * 0.5;
+    total.backward();
+
+    assert!((x.grad.get() - xs.grad.get()).abs() < 1e-12);
+    assert!((w.grad.get() - ws.grad.get()).abs() < 1e-12);
+    assert!((y1.grad.get() - y1s.grad.get()).abs() < 1e-12);
+    assert_eq!(y2.grad.get(), -1.0);
+
+    // overwrite replaces rather than adds
+    backward_many(&[(&y1, 2.0), (&y2, -1.0), (&y3, 0.5)], GradMode::Overwrite);
+    assert!((x.grad.get() - xs.grad.get()).abs() < 1e-12);
+}
+
+#[test]
+fn test_second_derivatives() {
+    // d/dx and d2/dx2 of each unary op, against central differences
+    type UnaryFn = fn(&Var) -> Var;
+    let fs: Vec<(UnaryFn, f64)> = vec![
+        (|x| x.neg(), 1.3),
+        (|x| x.pow(3.0), 1.3),
+        (|x| x.pow(-0.5), 1.3),
+        (|x| x.exp(), 0.4),
+        (|x| x.ln(), 1.7),
+        (|x| x.log(3.0), 1.7),
+        (|x| x.sqrt(), 2.2),
+        (|x| x.sin(), 0.9),
+        (|x| x.cos(), 0.9),
+        (|x| x.tan(), 0.6),
+        (|x| x.asin(), 0.3),
+        (|x| x.acos(), 0.3),
+        (|x| x.atan(), -1.4),
+        (|x| x.tanh(), 0.7),
+        (|x| x.relu(), 0.7),
+        (|x| x.sigmoid(), -0.6),
+        (|x| x.gelu(), 0.5),
+        (|x| x.softplus(), -0.5),
+        (|x| x.leaky_relu(0.1), -0.7),
+        (|x| x.elu(0.8), -0.7),
+        (|x| x.swish(), 1.1),
+        (|x| x / (x * x + 1.0), 0.8),
+        (|x| 2.0 - x * x, 0.8),
+    ];
+    let d1 = |f: UnaryFn, p: f64| {
+        let x = new_var(p);
+        return grad(&f(&x), &[&x])[0].value();
+    };
+    let eps = 1e-5;
+    for (f, p) in fs {
+        let x = new_var(p);
+        let y = f(&x);
+        let dy = grad(&y, &[&x]).remove(0);
+        let ddy = grad(&dy, &[&x]).remove(0);
+        let num1 = (f(&new_var(p + eps)).value() - f(&new_var(p - eps)).value()) / (2.0 * eps);
+        let num2 = (d1(f, p + eps) - d1(f, p - eps)) / (2.0 * eps);
+        assert!((dy.value() - num1).abs() < 1e-6, "first derivative at {}: {} vs {}", p, dy.value(), num1);
+        assert!((ddy.value() - num2).abs() < 1e-5, "second derivative at {}: {} vs {}", p, ddy.value(), num2);
+    }
+}
+
+#[test]
+fn test_mixed_partials_and_hvp() {
+    // f = x^2 y + sin(x y); d2f/dxdy = 2x + cos(xy) - xy sin(xy)
+    let x = new_var(0.7);
+    let y = new_var(-1.2);
+    let xy = &x * &y;
+    let f = &x * &x * &y + xy.sin();
+    let g = grad(&f, &[&x, &y]);
+    let gxy = grad(&g[0], &[&y]).remove(0);
+    let (xv, yv) = (0.7f64, -1.2f64);
+    let expected = 2.0 * xv + (xv * yv).cos() - xv * yv * (xv * yv).sin();
+    assert!((gxy.value() - expected).abs() < 1e-12);
+
+    // Hessian-vector product H v = grad(g . v)
+    let v = [1.0, 2.0];
+    let gv = &g[0] * v[0] + &g[1] * v[1];
+    let hv = grad(&gv, &[&x, &y]);
+    let hxx = grad(&g[0], &[&x]).remove(0).value();
+    let hyy = grad(&g[1], &[&y]).remove(0).value();
+    assert!((hv[0].value() - (hxx * v[0] + gxy.value() * v[1])).abs() < 1e-12);
+    assert!((hv[1].value() - (gxy.value() * v[0] + hyy * v[1])).abs() < 1e-12);
+
+    // grad leaves the numeric gradients alone
+    assert_eq!(x.grad.get(), 0.0);
+}
+
+#[test]
+fn test_gradient_penalty() {
+    // loss = (df/dx)^2 with f = w x^2: df/dx = 2wx, dloss/dw = 8 w x^2
+    let w = new_var(1.5);
+    let x = new_var(2.0);
+    let f = &w * &x * &x;
+    let dfdx = grad(&f, &[&x]).remove(0);
+    let penalty = &dfdx * &dfdx;
+    penalty.backward();
+    assert_eq!(penalty.value(), 36.0);
+    assert_eq!(w.grad.get(), 48.0);
+
+    // inputs the output does not depend on get zero
+    let z = new_var(3.0);
+    assert_eq!(grad(&f, &[&z])[0].value(), 0.0);
+}
+
+#[test]
+fn test_f32_graph() {
+    let a = new_var(2.0f32);
+    let b = new_var(0.5f32);
+    let y = (&a * &b).tanh() + a.pow(2.0) / &b - 1.0f32;
+    y.backward();
+    let t = 1.0f32.tanh();
+    assert_eq!(y.value(), t + 8.0 - 1.0);
+    assert!((a.grad.get() - ((1.0 - t * t) * 0.5 + 8.0)).abs() < 1e-5);
+    assert!((b.grad.get() - ((1.0 - t * t) * 2.0 - 16.0)).abs() < 1e-5);
+
+    let g = grad(&y, &[&a]).remove(0);
+    let gg = grad(&g, &[&a]).remove(0);
+    assert!((g.value() - a.grad.get()).abs() < 1e-5);
+    assert!(gg.value().is_finite());
+
+    // f32 and f64 graphs agree to f32 precision
+    let a64 = new_var(2.0);
+    let b64 = new_var(0.5);
+    let y64 = (&a64 * &b64).tanh() + a64.pow(2.0) / &b64 - 1.0;
+    y64.backward();
+    assert!((a64.grad.get() as f32 - a.grad.get()).abs() < 1e-5);
+}
+
+#[test]
+fn test_detach() {
+    // y = x * stop_gradient(x): only the first factor is differentiated
+    let x = new_var(3.0);
+    let y = &x * &x.detach();
+    y.backward();
+    assert_eq!(y.value(), 9.0);
+    assert_eq!(x.grad.get(), 3.0);
+    assert!(!x.detach().requires_grad());
+    assert!(y.requires_grad());
+
+    // and it stops gradient in graph-building mode too
+    let dy = grad(&y, &[&x]).remove(0);
+    assert_eq!(dy.value(), 3.0);
+    assert!(!dy.requires_grad());
+}
+
+#[test]
+fn test_new_const() {
+    let c = new_const(2.0);
+    let x = new_var(5.0);
+    let y = &c * &x + c.exp();
+    y.backward();
+    assert_eq!(x.grad.get(), 2.0);
+    assert_eq!(c.grad.get(), 0.0);
+
+    // a graph made only of constants has nothing to differentiate
+    let k = c.pow(2.0) * 3.0;
+    assert!(!k.requires_grad());
+    k.backward();
+    assert_eq!(k.grad.get(), 0.0);
+    assert_eq!(c.grad.get(), 0.0);
+}
+
+#[test]
+fn test_requires_grad_skips_work() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    struct Counted(Rc<Cell<usize>>);
+
+    impl Operation for Counted{
+        fn op(&self, x: &[f64]) -> f64 { return x[0]; }
+        fn grad(&self, _: &[f64], _: f64, _: usize) -> f64 {
+            self.0.set(self.0.get() + 1);
+            return 1.0;
+        }
+    }
+
+    let calls = Rc::new(Cell::new(0));
+    let frozen = new_var(1.0);
+    let w = new_var(2.0);
+    let mut h = frozen.clone();
+    for _ in 0..10 {
+        h = Var::apply(Counted(calls.clone()), &[&h]);
+    }
+    frozen.set_requires_grad(false);
+    // the chain was built while `frozen` required a gradient, so it is still
+    // walked, but nothing reaches the frozen leaf: the link into it is skipped
+    let y = &h * &w;
+    y.backward();
+    assert_eq!(calls.get(), 9);
+    assert_eq!(frozen.grad.get(), 0.0);
+    assert_eq!(w.grad.get(), 1.0);
+
+    // built after freezing, the chain requires no gradient and is skipped
+    let mut h = frozen.clone();
+    for _ in 0..10 {
+        h = Var::apply(Counted(calls.clone()), &[&h]);
+    }
+    let y = &h * &w;
+    y.backward();
+    assert_eq!(calls.get(), 9);
+    assert_eq!(w.grad.get(), 2.0);
+
+    frozen.set_requires_grad(true);
+    let y = Var::apply(Counted(calls.clone()), &[&frozen]);
+    y.backward();
+    assert_eq!(frozen.grad.get(), 1.0);
+}
+
+#[test]
+#[should_panic(expected = "non-leaf")]
+fn test_set_requires_grad_non_leaf() {
+    let x = new_var(1.0);
+    x.exp().set_requires_grad(false);
+}
+
+#[test]
+fn test_no_grad() {
+    let w = new_var(2.0);
+    let x = new_var(3.0);
+    let y = no_grad(|| {
+        assert!(!is_grad_enabled());
+        // nested scopes restore the outer (disabled) state
+        let inner = no_grad(|| w.exp());
+        assert!(!is_grad_enabled());
+        return (&w * &x).tanh() + inner;
+    });
+    assert!(is_grad_enabled());
+    assert_eq!(y.value(), 6f64.tanh() + 2f64.exp());
+    assert!(y.children().is_empty());
+    assert!(!y.requires_grad());
+    y.backward();
+    assert_eq!(w.grad.get(), 0.0);
+
+    // recording resumes afterwards, and a result from the scope acts as a constant
+    let z = &w * &y;
+    z.backward();
+    assert_eq!(w.grad.get(), y.value());
+}
+
+#[test]
+fn test_no_grad_restores_on_panic() {
+    let caught = std::panic::catch_unwind(|| no_grad(|| panic!("inside no_grad")));
+    assert!(caught.is_err());
+    assert!(is_grad_enabled());
+}
+
+#[test]
+fn test_hooks_modify_gradient() {
+    // gradient reversal: y = -1 * d(x * w)/dx flows into x, but w is unaffected
+    let x = new_var(3.0);
+    let w = new_var(2.0);
+    let h = &x * &w;
+    h.register_hook(|g| -g);
+    let y = &h * 5.0;
+    y.backward();
+    assert_eq!(h.grad.get(), -5.0);
+    assert_eq!(x.grad.get(), -10.0);
+    assert_eq!(w.grad.get(), -15.0);
+
+    // clipping on a leaf changes only what is stored there
+    let a = new_var(4.0);
+    a.register_hook(|g: f64| g.clamp(-1.0, 1.0));
+    let z = a.pow(2.0);
+    z.backward();
+    assert_eq!(a.grad.get(), 1.0);
+    // and sees only this pass's contribution, not the accumulated gradient
+    z.backward();
+    assert_eq!(a.grad.get(), 2.0);
+
+    a.clear_hooks();
+    a.zero_grad();
+    z.backward();
+    assert_eq!(a.grad.get(), 8.0);
+}
+
+#[test]
+fn test_hook_order() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let logger = |name: &'static str| {
+        let log = log.clone();
+        return move |g: f64| {
+            log.borrow_mut().push((name, g));
+            return g;
+        };
+    };
+    // x feeds both a and b, which feed y; x's hook runs once with the sum
+    let x = new_var(1.0);
+    let a = &x * 2.0;
+    let b = &x * 3.0;
+    let y = &a + &b;
+    x.register_hook(logger("x"));
+    b.register_hook(logger("b"));
+    a.register_hook(logger("a"));
+    y.register_hook(logger("y1"));
+    y.register_hook(|g| g * 10.0);
+    y.register_hook(logger("y2"));
+    y.backward();
+
+    let log = log.borrow();
+    assert_eq!(&log[..2], &[("y1", 1.0), ("y2", 10.0)]);
+    assert!(log[2..4].contains(&("a", 10.0)) && log[2..4].contains(&("b", 10.0)));
+    assert_eq!(log[4], ("x", 50.0));
+    assert_eq!(x.grad.get(), 50.0);
+}
+
+#[test]
+fn test_set_value() {
+    let p = new_var(1.0);
+    let q = &p * &p;
+    p.set_value(3.0);
+    assert_eq!(p.value(), 3.0);
+    // the old graph still holds the old product
+    assert_eq!(q.value(), 1.0);
+    assert_eq!((&p * &p).value(), 9.0);
+}
+
+#[test]
+#[should_panic(expected = "non-leaf")]
+fn test_set_value_non_leaf() {
+    let p = new_var(1.0);
+    p.exp().set_value(2.0);
+}
+
+#[test]
+fn test_hooks_change_own_hooks() {
+    // a one-shot hook that clears itself
+    let x = new_var(2.0);
+    let h = x.exp();
+    let h2 = h.clone();
+    h.register_hook(move |g| {
+        h2.clear_hooks();
+        return g * 0.0;
+    });
+    h.backward();
+    assert_eq!(x.grad.get(), 0.0);
+    h.backward();
+    assert_eq!(x.grad.get(), 2f64.exp());
+
+    // a hook that registers another, which runs from the next pass on
+    let y = new_var(1.0);
+    let k = &y * 2.0;
+    let k2 = k.clone();
+    let added = std::rc::Rc::new(Cell::new(false));
+    let flag = added.clone();
+    k.register_hook(move |g| {
+        if !flag.replace(true) { k2.register_hook(|g| g * 10.0); }
+        return g;
+    });
+    k.backward();
+    assert_eq!(y.grad.get(), 2.0);
+    k.backward();
+    assert_eq!(y.grad.get(), 22.0);
+}
@@ -0,0 +1,330 @@
+use crate::{new_var, Float, Var};
+
+/// Anything that owns trainable parameters. Implementors only list their
+/// parameters with a name; enumeration, gradient reset and freezing are built
+/// on that, so optimizers and serializers can work over any model.
+pub trait Module<T: Float = f64>{
+    /// Every parameter with its dot-separated path inside this module, e.g.
+    /// `layers.0.neurons.3.w.2`. Paths are unique and the order is stable.
+    fn named_parameters(&self) -> Vec<(String, Var<T>)>;
+
+    fn parameters(&self) -> Vec<Var<T>>{
+        return self.named_parameters().into_iter().map(|(_, p)| p).collect();
+    }
+
+    /// The parameters that are not frozen.
+    fn trainable_parameters(&self) -> Vec<Var<T>>{
+        return self.parameters().into_iter().filter(|p| p.requires_grad()).collect();
+    }
+
+    /// The parameter at `name`, if there is one.
+    fn parameter(&self, name: &str) -> Option<Var<T>>{
+        return self.named_parameters().into_iter().find(|(n, _)| n == name).map(|(_, p)| p);
+    }
+
+    fn zero_grad(&self){
+        for p in self.parameters() { p.grad.set(T::zero()); }
+    }
+
+    /// Stops every parameter from receiving gradient; see
+    /// [`Var::set_requires_grad`].
+    fn freeze(&self){
+        for p in self.parameters() { p.set_requires_grad(false); }
+    }
+
+    fn unfreeze(&self){
+        for p in self.parameters() { p.set_requires_grad(true); }
+    }
+
+    /// Freezes the parameter at `name`. Panics if there is none.
+    fn freeze_parameter(&self, name: &str){
+        expect_parameter(self, name).set_requires_grad(false);
+    }
+
+    /// Unfreezes the parameter at `name`. Panics if there is none.
+    fn unfreeze_parameter(&self, name: &str){
+        expect_parameter(self, name).set_requires_grad(true);
+    }
+}
+
+fn expect_parameter<T: Float, M: Module<T> + ?Sized>(module: &M, name: &str) -> Var<T>{
+    return module.parameter(name).unwrap_or_else(|| panic!("no parameter named `{}`", name));
+}
+
+/// The named parameters of `module` with `name.` put in front of each path,
+/// for use in [`Module::named_parameters`] of a containing module.
+pub fn scoped<T: Float>(name: &str, module: &impl Module<T>) -> Vec<(String, Var<T>)>{
+    return module.named_parameters().into_iter().map(|(n, p)| (format!("{}.{}", name, n), p)).collect();
+}
+
+// A list of bare parameters, such as a neuron's weights, named by index.
+fn indexed<T: Float>(name: &str, params: &[Var<T>]) -> Vec<(String, Var<T>)>{
+    return params.iter().enumerate().map(|(i, p)| (format!("{}.{}", name, i), p.clone())).collect();
+}
+
+// A list of modules names its elements by index.
+impl<T: Float, M: Module<T>> Module<T> for Vec<M>{
+    fn named_parameters(&self) -> Vec<(String, Var<T>)>{
+        return self.iter().enumerate().flat_map(|(i, m)| scoped(&i.to_string(), m)).collect();
+    }
+}
+
+/// A small, seedable xorshift64* generator for initializing weights, so that
+/// models are reproducible without pulling in a dependency.
+#[derive(Clone, Debug)]
+pub struct Rng{
+    state: u64,
+}
+
+impl Rng{
+    pub fn new(seed: u64) -> Rng{
+        // xorshift gets stuck on a zero state
+        return Rng{ state: seed ^ 0x9E37_79B9_7F4A_7C15 };
+    }
+
+    pub fn next_u64(&mut self) -> u64{
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        return self.state.wrapping_mul(0x2545_F491_4F6C_DD1D);
+    }
+
+    /// Uniform in `[lo, hi)`.
+    pub fn uniform(&mut self, lo: f64, hi: f64) -> f64{
+        // the top 53 bits give every representable multiple of 2^-53 in [0, 1)
+        let unit = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        return lo + (hi - lo) * unit;
+    }
+}
+
+/// The nonlinearity a [`Neuron`] applies to its weighted sum.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Activation{
+    /// No nonlinearity, e.g. for an output layer.
+    Linear,
+    Tanh,
+    Relu,
+    Sigmoid,
+    Gelu,
+    Softplus,
+    Swish,
+    LeakyRelu(f64),
+    Elu(f64),
+}
+
+impl Activation{
+    pub fn apply<T: Float>(&self, x: &Var<T>) -> Var<T>{
+        return match *self {
+            Activation::Linear => x.clone(),
+            Activation::Tanh => x.tanh(),
+            Activation::Relu => x.relu(),
+            Activation::Sigmoid => x.sigmoid(),
+            Activation::Gelu => x.gelu(),
+            Activation::Softplus => x.softplus(),
+            Activation::Swish => x.swish(),
+            Activation::LeakyRelu(slope) => x.leaky_relu(slope),
+            Activation::Elu(alpha) => x.elu(alpha),
+        };
+    }
+}
+
+/// `activation(w . x + b)`, with the weights and bias as leaf `Var`s.
+pub struct Neuron<T: Float = f64>{
+    pub w: Vec<Var<T>>,
+    pub b: Var<T>,
+    pub activation: Activation,
+}
+
+impl<T: Float> Neuron<T>{
+    /// A neuron with `nin` inputs, weights drawn uniformly from [-1, 1) and a
+    /// zero bias.
+    pub fn new(nin: usize, activation: Activation, rng: &mut Rng) -> Neuron<T>{
+        let w = (0..nin).map(|_| new_var(T::from_f64(rng.uniform(-1.0, 1.0)))).collect();
+        return Neuron{ w, b: new_var(T::zero()), activation };
+    }
+
+    /// Panics if `x` does not have one value per weight.
+    pub fn forward(&self, x: &[Var<T>]) -> Var<T>{
+        assert_eq!(x.len(), self.w.len(), "Neuron expects {} inputs", self.w.len());
+        let mut sum = self.b.clone();
+        for (w, x) in self.w.iter().zip(x) {
+            sum = &sum + w * x;
+        }
+        return self.activation.apply(&sum);
+    }
+}
+
+// weights `w.0`, `w.1`, ... followed by the bias `b`
+impl<T: Float> Module<T> for Neuron<T>{
+    fn named_parameters(&self) -> Vec<(String, Var<T>)>{
+        let mut params = indexed("w", &self.w);
+        params.push(("b".to_string(), self.b.clone()));
+        return params;
+    }
+}
+
+/// Several neurons reading the same inputs.
+pub struct Layer<T: Float = f64>{
+    pub neurons: Vec<Neuron<T>>,
+}
+
+impl<T: Float> Layer<T>{
+    pub fn new(nin: usize, nout: usize, activation: Activation, rng: &mut Rng) -> Layer<T>{
+        return Layer{ neurons: (0..nout).map(|_| Neuron::new(nin, activation, rng)).collect() };
+    }
+
+    /// One output per neuron.
+    pub fn forward(&self, x: &[Var<T>]) -> Vec<Var<T>>{
+        return self.neurons.iter().map(|n| n.forward(x)).collect();
+    }
+}
+
+impl<T: Float> Module<T> for Layer<T>{
+    fn named_parameters(&self) -> Vec<(String, Var<T>)>{
+        return scoped("neurons", &self.neurons);
+    }
+}
+
+/// A multi-layer perceptron: layers applied one after the other.
+pub struct MLP<T: Float = f64>{
+    pub layers: Vec<Layer<T>>,
+}
+
+impl<T: Float> MLP<T>{
+    /// Layers of sizes `nouts` on top of `nin` inputs. Hidden layers use
+    /// `activation` and the last one is linear, so the outputs are unbounded
+    /// scores; apply a nonlinearity to them if one is wanted.
+    pub fn new(nin: usize, nouts: &[usize], activation: Activation, rng: &mut Rng) -> MLP<T>{
+        let sizes: Vec<usize> = std::iter::once(nin).chain(nouts.iter().copied()).collect();
+        let layers = sizes.windows(2).enumerate().map(|(k, s)| {
+            let act = if k + 1 == nouts.len() { Activation::Linear } else { activation };
+            return Layer::new(s[0], s[1], act, rng);
+        }).collect();
+        return MLP{ layers };
+    }
+
+    pub fn forward(&self, x: &[Var<T>]) -> Vec<Var<T>>{
+        let mut x = x.to_vec();
+        for layer in self.layers.iter() {
+            x = layer.forward(&x);
+        }
+        return x;
+    }
+}
+
+impl<T: Float> Module<T> for MLP<T>{
+    fn named_parameters(&self) -> Vec<(String, Var<T>)>{
+        return scoped("layers", &self.layers);
+    }
+}
+
+#[test]
+fn test_neuron_forward_backward() {
+    let n = Neuron{ w: vec![new_var(0.5), new_var(-1.0)], b: new_var(0.25), activation: Activation::Tanh };
+    let x = [new_var(2.0), new_var(3.0)];
+    let y = n.forward(&x);
+    let s = 0.5 * 2.0 - 3.0 + 0.25;
+    assert_eq!(y.value(), f64::tanh(s));
+    y.backward();
+    let d = 1.0 - y.value() * y.value();
+    assert_eq!(n.w[0].grad.get(), d * 2.0);
+    assert_eq!(n.w[1].grad.get(), d * 3.0);
+    assert_eq!(n.b.grad.get(), d);
+    assert_eq!(x[1].grad.get(), -d);
+    assert_eq!(n.parameters().len(), 3);
+}
+
+#[test]
+fn test_mlp_shapes() {
+    let mut rng = Rng::new(1);
+    let mlp: MLP = MLP::new(3, &[4, 4, 1], Activation::Relu, &mut rng);
+    // (3 + 1) * 4 + (4 + 1) * 4 + (4 + 1) * 1
+    assert_eq!(mlp.parameters().len(), 41);
+    assert_eq!(mlp.layers[0].neurons[0].activation, Activation::Relu);
+    assert_eq!(mlp.layers[2].neurons[0].activation, Activation::Linear);
+    assert!(mlp.parameters().iter().all(|p| p.value() >= -1.0 && p.value() < 1.0));
+
+    let x: Vec<Var> = [1.0, -2.0, 0.5].iter().map(|&v| new_var(v)).collect();
+    let y = mlp.forward(&x);
+    assert_eq!(y.len(), 1);
+
+    // the same seed gives the same model
+    let again: MLP = MLP::new(3, &[4, 4, 1], Activation::Relu, &mut Rng::new(1));
+    assert_eq!(again.forward(&x)[0].value(), y[0].value());
+}
+
+#[test]
+fn test_mlp_training() {
+    use crate::Optimizer;
+
+    // the classic micrograd demo: fit four points with a 3-4-4-1 tanh network
+    let xs = [[2.0, 3.0, -1.0], [3.0, -1.0, 0.5], [0.5, 1.0, 1.0], [1.0, 1.0, -1.0]];
+    let ys = [1.0, -1.0, -1.0, 1.0];
+    let mlp: MLP = MLP::new(3, &[4, 4, 1], Activation::Tanh, &mut Rng::new(42));
+    let loss_of = |mlp: &MLP| {
+        let mut loss = new_var(0.0);
+        for (x, &y) in xs.iter().zip(&ys) {
+            let x: Vec<Var> = x.iter().map(|&v| new_var(v)).collect();
+            let d = &mlp.forward(&x)[0] - y;
+            loss = loss + &d * &d;
+        }
+        return loss;
+    };
+    let first = loss_of(&mlp).value();
+    let mut opt = crate::SGD::new(mlp.parameters(), 0.05);
+    for _ in 0..100 {
+        let loss = loss_of(&mlp);
+        opt.zero_grad();
+        loss.backward();
+        opt.step();
+    }
+    let last = loss_of(&mlp).value();
+    assert!(last < 0.05 && last < first, "{} -> {}", first, last);
+}
+
+#[test]
+fn test_named_parameters() {
+    let mlp: MLP = MLP::new(2, &[3, 1], Activation::Tanh, &mut Rng::new(7));
+    let named = mlp.named_parameters();
+    let names: Vec<&str> = named.iter().map(|(n, _)| n.as_str()).collect();
+    assert_eq!(&names[..4], &["layers.0.neurons.0.w.0", "layers.0.neurons.0.w.1", "layers.0.neurons.0.b", "layers.0.neurons.1.w.0"]);
+    assert_eq!(names.last(), Some(&"layers.1.neurons.0.b"));
+    assert_eq!(names.len(), 3 * 3 + 4);
+
+    // paths point at the actual leaves
+    let w = mlp.parameter("layers.1.neurons.0.w.2").unwrap();
+    assert!(std::rc::Rc::ptr_eq(&w.0, &mlp.layers[1].neurons[0].w[2].0));
+    assert!(mlp.parameter("layers.1.neurons.1.b").is_none());
+    assert_eq!(mlp.layers[0].named_parameters()[0].0, "neurons.0.w.0");
+}
+
+#[test]
+fn test_module_zero_grad_and_freeze() {
+    let mlp: MLP = MLP::new(2, &[2, 1], Activation::Tanh, &mut Rng::new(3));
+    let x = [new_var(0.5), new_var(-1.5)];
+
+    mlp.layers[0].freeze();
+    mlp.freeze_parameter("layers.1.neurons.0.b");
+    assert_eq!(mlp.trainable_parameters().len(), 2);
+    let y = mlp.forward(&x)[0].clone();
+    y.backward();
+    for (name, p) in mlp.named_parameters() {
+        let got = p.grad.get() != 0.0;
+        assert_eq!(got, name.starts_with("layers.1.neurons.0.w"), "{}", name);
+    }
+
+    mlp.zero_grad();
+    assert!(mlp.parameters().iter().all(|p| p.grad.get() == 0.0));
+
+    mlp.unfreeze();
+    assert_eq!(mlp.trainable_parameters().len(), mlp.parameters().len());
+    mlp.forward(&x)[0].backward();
+    assert!(mlp.parameter("layers.0.neurons.1.b").unwrap().grad.get() != 0.0);
+}
+
+#[test]
+#[should_panic(expected = "no parameter named `layers.9.b`")]
+fn test_freeze_unknown_parameter() {
+    let mlp: MLP = MLP::new(1, &[1], Activation::Linear, &mut Rng::new(0));
+    mlp.freeze_parameter("layers.9.b");
+}
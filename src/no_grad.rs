@@ -0,0 +1,29 @@
+use std::cell::Cell;
+
+thread_local! {
+    static GRAD_ENABLED: Cell<bool> = const { Cell::new(true) };
+}
+
+// Restores the previous state when the scope ends, including by unwinding.
+struct Restore(bool);
+
+impl Drop for Restore{
+    fn drop(&mut self){
+        GRAD_ENABLED.with(|e| e.set(self.0));
+    }
+}
+
+/// Runs `f` with graph recording turned off on this thread. Ops inside compute
+/// their value as usual but return a constant leaf: no children are linked and
+/// no operation is stored, so nothing can be backpropagated through the result.
+/// Scopes nest, and the previous state is restored when `f` returns or panics.
+pub fn no_grad<R>(f: impl FnOnce() -> R) -> R{
+    let _restore = Restore(GRAD_ENABLED.with(|e| e.replace(false)));
+    return f();
+}
+
+/// Whether ops record a graph on this thread, i.e. whether we are outside
+/// every [`no_grad`] scope.
+pub fn is_grad_enabled() -> bool{
+    return GRAD_ENABLED.with(|e| e.get());
+}
@@ -0,0 +1,504 @@
+/// A differentiable scalar function of any number of inputs.
+///
+/// `x` holds the input values in the order they were passed to
+/// [`Var::apply`](crate::Var::apply); `out` is the result of `op` on them, for
+/// ops whose derivative is cheapest in terms of their own output.
+pub trait Operation<T: Float = f64>{
+    fn op(&self, x: &[T]) -> T;
+    /// Partial derivative of `op` with respect to `x[i]`.
+    fn grad(&self, x: &[T], out: T, i: usize) -> T;
+
+    /// `g` times the partial derivative with respect to `x[i]`, built out of
+    /// `Var` ops so that [`grad`](crate::grad) can differentiate it again; `out`
+    /// is the node this op produced.
+    ///
+    /// The default evaluates [`Operation::grad`] and uses it as a constant, which
+    /// is right for first derivatives but leaves out this op's own second-order
+    /// terms. Override it to support higher-order derivatives.
+    fn grad_graph(&self, x: &[Var<T>], out: &Var<T>, g: &Var<T>, i: usize) -> Var<T> {
+        let v: Vec<T> = x.iter().map(|c| c.value()).collect();
+        return g * self.grad(&v, out.value(), i);
+    }
+}
+
+use crate::{Float, Var};
+
+// The built-in op methods, shared by every value type that evaluates
+// `Operation`s (`Var`, `Dual`) so that their op sets cannot drift apart. The
+// type provides `unary(&self, op) -> Self` and `binary(&self, &Self, op) -> Self`.
+macro_rules! op_methods {
+    () => {
+        pub fn add(&self, o: &Self) -> Self {
+            return self.binary(o, $crate::ops::AddOP{});
+        }
+
+        pub fn neg(&self) -> Self {
+            return self.unary($crate::ops::NegOp{});
+        }
+
+        pub fn sub(&self, o: &Self) -> Self {
+            return self.binary(o, $crate::ops::SubOp{});
+        }
+
+        pub fn mul(&self, o: &Self) -> Self {
+            return self.binary(o, $crate::ops::MulOp{});
+        }
+
+        /// Dividing by a zero-valued `o` does not panic: the value and the
+        /// derivatives follow IEEE float rules and come out infinite or NaN.
+        pub fn div(&self, o: &Self) -> Self {
+            return self.binary(o, $crate::ops::DivOp{});
+        }
+
+        pub fn pow(&self, p: f64) -> Self{
+            return self.unary($crate::ops::PowOp{ p });
+        }
+
+        pub fn exp(&self) -> Self{
+            return self.unary($crate::ops::ExpOp{});
+        }
+
+        /// Natural logarithm. Non-positive inputs do not panic: `ln(0)` is -inf with
+        /// an infinite gradient and negative inputs give NaN.
+        pub fn ln(&self) -> Self{
+            return self.unary($crate::ops::LnOp{});
+        }
+
+        /// Logarithm in the given base, with the same domain handling as `ln`.
+        pub fn log(&self, base: f64) -> Self{
+            return self.unary($crate::ops::LogOp{ base });
+        }
+
+        /// Square root; NaN for negative inputs and an infinite gradient at 0.
+        pub fn sqrt(&self) -> Self{
+            return self.unary($crate::ops::SqrtOp{});
+        }
+
+        pub fn sin(&self) -> Self{
+            return self.unary($crate::ops::SinOp{});
+        }
+
+        pub fn cos(&self) -> Self{
+            return self.unary($crate::ops::CosOp{});
+        }
+
+        pub fn tan(&self) -> Self{
+            return self.unary($crate::ops::TanOp{});
+        }
+
+        /// Arcsine; NaN outside [-1, 1] and an infinite gradient at the endpoints.
+        pub fn asin(&self) -> Self{
+            return self.unary($crate::ops::AsinOp{});
+        }
+
+        /// Arccosine; NaN outside [-1, 1] and an infinite gradient at the endpoints.
+        pub fn acos(&self) -> Self{
+            return self.unary($crate::ops::AcosOp{});
+        }
+
+        pub fn atan(&self) -> Self{
+            return self.unary($crate::ops::AtanOp{});
+        }
+
+        pub fn tanh(&self) -> Self{
+            return self.unary($crate::ops::TanhOp{});
+        }
+
+        pub fn relu(&self) -> Self{
+            return self.unary($crate::ops::ReluOp{});
+        }
+
+        pub fn sigmoid(&self) -> Self{
+            return self.unary($crate::ops::SigmoidOp{});
+        }
+
+        /// GELU, tanh approximation.
+        pub fn gelu(&self) -> Self{
+            return self.unary($crate::ops::GeluOp{});
+        }
+
+        pub fn softplus(&self) -> Self{
+            return self.unary($crate::ops::SoftplusOp{});
+        }
+
+        pub fn leaky_relu(&self, slope: f64) -> Self{
+            return self.unary($crate::ops::LeakyReluOp{ slope });
+        }
+
+        pub fn elu(&self, alpha: f64) -> Self{
+            return self.unary($crate::ops::EluOp{ alpha });
+        }
+
+        /// `x * sigmoid(x)`, also known as SiLU.
+        pub fn swish(&self) -> Self{
+            return self.unary($crate::ops::SwishOp{});
+        }
+    };
+}
+
+/// An [`Operation`] built from a pair of closures, see [`new_op`].
+pub struct FnOp<F, G>{
+    forward: F,
+    grad: G,
+}
+
+/// Builds an operation from a forward closure and a closure computing the
+/// partial derivative with respect to input `i`.
+///
+/// The result uses the default [`Operation::grad_graph`], which treats the
+/// partial derivative as a constant: first derivatives are exact, but
+/// [`grad`](crate::grad) applied twice leaves out the op's own second-order
+/// terms. Implement [`Operation`] directly to support higher-order derivatives.
+pub fn new_op<T, F, G>(forward: F, grad: G) -> FnOp<F, G>
+where
+    T: Float,
+    F: Fn(&[T]) -> T,
+    G: Fn(&[T], T, usize) -> T,
+{
+    return FnOp{ forward, grad };
+}
+
+impl<T, F, G> Operation<T> for FnOp<F, G>
+where
+    T: Float,
+    F: Fn(&[T]) -> T,
+    G: Fn(&[T], T, usize) -> T,
+{
+    fn op(&self, x: &[T]) -> T { return (self.forward)(x); }
+    fn grad(&self, x: &[T], out: T, i: usize) -> T { return (self.grad)(x, out, i); }
+}
+
+pub(crate) struct NoOP;
+pub struct AddOP;
+pub struct NegOp;
+pub struct SubOp;
+pub struct MulOp;
+/// Division follows IEEE semantics: dividing by zero yields an infinite or NaN
+/// value and gradient rather than panicking.
+pub struct DivOp;
+pub struct PowOp{
+    pub p: f64,
+}
+
+// Transcendental functions. Outside their real domain (e.g. `ln` of a negative
+// number, `asin` beyond [-1, 1]) they produce NaN like the f64 methods they
+// wrap, and at a boundary such as `ln(0)` they produce an infinite value and
+// gradient; none of them panic.
+pub struct ExpOp;
+pub struct LnOp;
+pub struct LogOp{
+    pub base: f64,
+}
+pub struct SqrtOp;
+pub struct SinOp;
+pub struct CosOp;
+pub struct TanOp;
+pub struct AsinOp;
+pub struct AcosOp;
+pub struct AtanOp;
+
+// Activations. Tanh, sigmoid and the ones built on them take their gradient from
+// the forward output instead of evaluating the function again.
+pub struct TanhOp;
+pub struct ReluOp;
+pub struct SigmoidOp;
+/// GELU using the tanh approximation, as there is no `erf` in std.
+pub struct GeluOp;
+pub struct SoftplusOp;
+pub struct LeakyReluOp{
+    pub slope: f64,
+}
+pub struct EluOp{
+    pub alpha: f64,
+}
+pub struct SwishOp;
+
+// small constants in generic code
+fn c<T: Float>(v: f64) -> T { return T::from_f64(v); }
+
+impl<T: Float> Operation<T> for NoOP{
+    fn op(&self, _: &[T]) -> T { return T::zero() }
+    fn grad(&self, _: &[T], _: T, _: usize) -> T { return T::zero() }
+}
+
+impl<T: Float> Operation<T> for AddOP{
+    fn op(&self, x: &[T]) -> T { return x[0] + x[1] }
+    fn grad(&self, _: &[T], _: T, _: usize) -> T { return T::one(); }
+    fn grad_graph(&self, _: &[Var<T>], _: &Var<T>, g: &Var<T>, _: usize) -> Var<T> { return g.clone(); }
+}
+
+impl<T: Float> Operation<T> for NegOp{
+    fn op(&self, x: &[T]) -> T { return -x[0]; }
+    fn grad(&self, _: &[T], _: T, _: usize) -> T { return -T::one(); }
+    fn grad_graph(&self, _: &[Var<T>], _: &Var<T>, g: &Var<T>, _: usize) -> Var<T> { return -g; }
+}
+
+impl<T: Float> Operation<T> for SubOp{
+    fn op(&self, x: &[T]) -> T { return x[0] - x[1] }
+    fn grad(&self, _: &[T], _: T, i: usize) -> T { return if i == 0 { T::one() } else { -T::one() }; }
+    fn grad_graph(&self, _: &[Var<T>], _: &Var<T>, g: &Var<T>, i: usize) -> Var<T> { return if i == 0 { g.clone() } else { -g }; }
+}
+
+impl<T: Float> Operation<T> for MulOp{
+    fn op(&self, x: &[T]) -> T { return x[0] * x[1]; }
+    fn grad(&self, x: &[T], _: T, i: usize) -> T { return x[1 - i]; }
+    fn grad_graph(&self, x: &[Var<T>], _: &Var<T>, g: &Var<T>, i: usize) -> Var<T> { return g * &x[1 - i]; }
+}
+
+impl<T: Float> Operation<T> for DivOp{
+    fn op(&self, x: &[T]) -> T { return x[0] / x[1]; }
+    fn grad(&self, x: &[T], _: T, i: usize) -> T {
+        return if i == 0 { T::one() / x[1] } else { -x[0] / (x[1] * x[1]) };
+    }
+    fn grad_graph(&self, x: &[Var<T>], out: &Var<T>, g: &Var<T>, i: usize) -> Var<T> {
+        return if i == 0 { g / &x[1] } else { -(g * out / &x[1]) };
+    }
+}
+
+impl<T: Float> Operation<T> for PowOp{
+    fn op(&self, x: &[T]) -> T { return x[0].powf(c::<T>(self.p)); }
+    fn grad(&self, x: &[T], _: T, _: usize) -> T { return c::<T>(self.p) * x[0].powf(c::<T>(self.p - 1.0)); }
+    fn grad_graph(&self, x: &[Var<T>], _: &Var<T>, g: &Var<T>, _: usize) -> Var<T> { return g * x[0].pow(self.p - 1.0) * c::<T>(self.p); }
+}
+
+impl<T: Float> Operation<T> for ExpOp{
+    fn op(&self, x: &[T]) -> T { return x[0].exp(); }
+    fn grad(&self, _: &[T], out: T, _: usize) -> T { return out; }
+    fn grad_graph(&self, _: &[Var<T>], out: &Var<T>, g: &Var<T>, _: usize) -> Var<T> { return g * out; }
+}
+
+impl<T: Float> Operation<T> for LnOp{
+    fn op(&self, x: &[T]) -> T { return x[0].ln(); }
+    fn grad(&self, x: &[T], _: T, _: usize) -> T { return T::one() / x[0]; }
+    fn grad_graph(&self, x: &[Var<T>], _: &Var<T>, g: &Var<T>, _: usize) -> Var<T> { return g / &x[0]; }
+}
+
+impl<T: Float> Operation<T> for LogOp{
+    fn op(&self, x: &[T]) -> T { return x[0].log(c::<T>(self.base)); }
+    fn grad(&self, x: &[T], _: T, _: usize) -> T { return T::one() / (x[0] * c::<T>(self.base.ln())); }
+    fn grad_graph(&self, x: &[Var<T>], _: &Var<T>, g: &Var<T>, _: usize) -> Var<T> { return g / (&x[0] * c::<T>(self.base.ln())); }
+}
+
+impl<T: Float> Operation<T> for SqrtOp{
+    fn op(&self, x: &[T]) -> T { return x[0].sqrt(); }
+    fn grad(&self, _: &[T], out: T, _: usize) -> T { return c::<T>(0.5) / out; }
+    fn grad_graph(&self, _: &[Var<T>], out: &Var<T>, g: &Var<T>, _: usize) -> Var<T> { return g / (out * c::<T>(2.0)); }
+}
+
+impl<T: Float> Operation<T> for SinOp{
+    fn op(&self, x: &[T]) -> T { return x[0].sin(); }
+    fn grad(&self, x: &[T], _: T, _: usize) -> T { return x[0].cos(); }
+    fn grad_graph(&self, x: &[Var<T>], _: &Var<T>, g: &Var<T>, _: usize) -> Var<T> { return g * x[0].cos(); }
+}
+
+impl<T: Float> Operation<T> for CosOp{
+    fn op(&self, x: &[T]) -> T { return x[0].cos(); }
+    fn grad(&self, x: &[T], _: T, _: usize) -> T { return -x[0].sin(); }
+    fn grad_graph(&self, x: &[Var<T>], _: &Var<T>, g: &Var<T>, _: usize) -> Var<T> { return -(g * x[0].sin()); }
+}
+
+impl<T: Float> Operation<T> for TanOp{
+    fn op(&self, x: &[T]) -> T { return x[0].tan(); }
+    fn grad(&self, _: &[T], out: T, _: usize) -> T { return T::one() + out * out; }
+    fn grad_graph(&self, _: &[Var<T>], out: &Var<T>, g: &Var<T>, _: usize) -> Var<T> { return g * (out * out + T::one()); }
+}
+
+impl<T: Float> Operation<T> for AsinOp{
+    fn op(&self, x: &[T]) -> T { return x[0].asin(); }
+    fn grad(&self, x: &[T], _: T, _: usize) -> T { return T::one() / (T::one() - x[0] * x[0]).sqrt(); }
+    fn grad_graph(&self, x: &[Var<T>], _: &Var<T>, g: &Var<T>, _: usize) -> Var<T> { return g / (-(&x[0] * &x[0]) + T::one()).sqrt(); }
+}
+
+impl<T: Float> Operation<T> for AcosOp{
+    fn op(&self, x: &[T]) -> T { return x[0].acos(); }
+    fn grad(&self, x: &[T], _: T, _: usize) -> T { return -T::one() / (T::one() - x[0] * x[0]).sqrt(); }
+    fn grad_graph(&self, x: &[Var<T>], _: &Var<T>, g: &Var<T>, _: usize) -> Var<T> { return -(g / (-(&x[0] * &x[0]) + T::one()).sqrt()); }
+}
+
+impl<T: Float> Operation<T> for AtanOp{
+    fn op(&self, x: &[T]) -> T { return x[0].atan(); }
+    fn grad(&self, x: &[T], _: T, _: usize) -> T { return T::one() / (T::one() + x[0] * x[0]); }
+    fn grad_graph(&self, x: &[Var<T>], _: &Var<T>, g: &Var<T>, _: usize) -> Var<T> { return g / (&x[0] * &x[0] + T::one()); }
+}
+
+fn sigmoid<T: Float>(x: T) -> T { return T::one() / (T::one() + (-x).exp()); }
+
+impl<T: Float> Operation<T> for TanhOp{
+    fn op(&self, x: &[T]) -> T { return x[0].tanh(); }
+    fn grad(&self, _: &[T], out: T, _: usize) -> T { return T::one() - out * out; }
+    fn grad_graph(&self, _: &[Var<T>], out: &Var<T>, g: &Var<T>, _: usize) -> Var<T> { return g * (-(out * out) + T::one()); }
+}
+
+// The default grad_graph is exact for ReLU and leaky ReLU: their derivative is
+// piecewise constant, so it has no second-order terms.
+impl<T: Float> Operation<T> for ReluOp{
+    fn op(&self, x: &[T]) -> T { return x[0].max(T::zero()); }
+    fn grad(&self, x: &[T], _: T, _: usize) -> T { return if x[0] > T::zero() { T::one() } else { T::zero() }; }
+}
+
+impl<T: Float> Operation<T> for SigmoidOp{
+    fn op(&self, x: &[T]) -> T { return sigmoid(x[0]); }
+    fn grad(&self, _: &[T], out: T, _: usize) -> T { return out * (T::one() - out); }
+    fn grad_graph(&self, _: &[Var<T>], out: &Var<T>, g: &Var<T>, _: usize) -> Var<T> { return g * out * (-out + T::one()); }
+}
+
+const GELU_C: f64 = 0.7978845608028654; // sqrt(2 / pi)
+const GELU_A: f64 = 0.044715;
+
+impl<T: Float> Operation<T> for GeluOp{
+    fn op(&self, x: &[T]) -> T {
+        let x = x[0];
+        return c::<T>(0.5) * x * (T::one() + (c::<T>(GELU_C) * (x + c::<T>(GELU_A) * x * x * x)).tanh());
+    }
+    fn grad(&self, x: &[T], _: T, _: usize) -> T {
+        let x = x[0];
+        let t = (c::<T>(GELU_C) * (x + c::<T>(GELU_A) * x * x * x)).tanh();
+        return c::<T>(0.5) * (T::one() + t)
+            + c::<T>(0.5) * x * (T::one() - t * t) * c::<T>(GELU_C) * (T::one() + c::<T>(3.0 * GELU_A) * x * x);
+    }
+    fn grad_graph(&self, x: &[Var<T>], _: &Var<T>, g: &Var<T>, _: usize) -> Var<T> {
+        let x = &x[0];
+        let x2 = x * x;
+        let t = ((x + &x2 * x * c::<T>(GELU_A)) * c::<T>(GELU_C)).tanh();
+        let d = (&t + T::one()) * c::<T>(0.5) + x * (-(&t * &t) + T::one()) * (x2 * c::<T>(3.0 * GELU_A) + T::one()) * c::<T>(0.5 * GELU_C);
+        return g * d;
+    }
+}
+
+impl<T: Float> Operation<T> for SoftplusOp{
+    // ln(1 + e^x), rearranged so that large |x| neither overflows nor loses precision
+    fn op(&self, x: &[T]) -> T { return x[0].max(T::zero()) + (-x[0].abs()).exp().ln_1p(); }
+    fn grad(&self, x: &[T], _: T, _: usize) -> T { return sigmoid(x[0]); }
+    fn grad_graph(&self, x: &[Var<T>], _: &Var<T>, g: &Var<T>, _: usize) -> Var<T> { return g * x[0].sigmoid(); }
+}
+
+impl<T: Float> Operation<T> for LeakyReluOp{
+    fn op(&self, x: &[T]) -> T { return if x[0] > T::zero() { x[0] } else { c::<T>(self.slope) * x[0] }; }
+    fn grad(&self, x: &[T], _: T, _: usize) -> T { return if x[0] > T::zero() { T::one() } else { c::<T>(self.slope) }; }
+}
+
+impl<T: Float> Operation<T> for EluOp{
+    fn op(&self, x: &[T]) -> T { return if x[0] > T::zero() { x[0] } else { c::<T>(self.alpha) * x[0].exp_m1() }; }
+    fn grad(&self, x: &[T], out: T, _: usize) -> T { return if x[0] > T::zero() { T::one() } else { out + c::<T>(self.alpha) }; }
+    fn grad_graph(&self, x: &[Var<T>], out: &Var<T>, g: &Var<T>, _: usize) -> Var<T> {
+        return if x[0].value() > T::zero() { g.clone() } else { g * (out + c::<T>(self.alpha)) };
+    }
+}
+
+impl<T: Float> Operation<T> for SwishOp{
+    fn op(&self, x: &[T]) -> T { return x[0] * sigmoid(x[0]); }
+    fn grad(&self, x: &[T], out: T, _: usize) -> T {
+        let s = sigmoid(x[0]);
+        return out + s * (T::one() - out);
+    }
+    fn grad_graph(&self, x: &[Var<T>], out: &Var<T>, g: &Var<T>, _: usize) -> Var<T> {
+        let s = x[0].sigmoid();
+        return g * (out + &s * (-out + T::one()));
+    }
+}
+
+#[test]
+fn test_op_grads() {
+    let x = [3.0, -2.0];
+    assert_eq!(AddOP{}.grad(&x, 1.0, 0), 1.0);
+    assert_eq!(AddOP{}.grad(&x, 1.0, 1), 1.0);
+    assert_eq!(MulOp{}.grad(&x, -6.0, 0), -2.0);
+    assert_eq!(MulOp{}.grad(&x, -6.0, 1), 3.0);
+    assert_eq!(SubOp{}.grad(&x, 5.0, 0), 1.0);
+    assert_eq!(SubOp{}.grad(&x, 5.0, 1), -1.0);
+    assert_eq!(DivOp{}.grad(&x, -1.5, 0), -0.5);
+    assert_eq!(DivOp{}.grad(&x, -1.5, 1), -0.75);
+    assert_eq!(NegOp{}.grad(&x[..1], -3.0, 0), -1.0);
+    assert_eq!(PowOp{ p: 3.0 }.grad(&x[..1], 27.0, 0), 27.0);
+    assert_eq!(PowOp{ p: -1.0 }.grad(&[2.0], 0.5, 0), -0.25);
+}
+
+#[test]
+fn test_fn_op() {
+    let hyp = new_op(|x: &[f64]| (x[0] * x[0] + x[1] * x[1]).sqrt(), |x, out, i| x[i] / out);
+    assert_eq!(hyp.op(&[3.0, 4.0]), 5.0);
+    assert_eq!(hyp.grad(&[3.0, 4.0], 5.0, 0), 0.6);
+    assert_eq!(hyp.grad(&[3.0, 4.0], 5.0, 1), 0.8);
+}
+
+#[test]
+fn test_fn_op_second_derivative() {
+    use crate::{grad, new_var, Var};
+
+    // x^3 as a closure op: the first derivative is right, but the second
+    // misses the op's own curvature and only sees the constant 3x^2
+    let cube = || new_op(|x: &[f64]| x[0] * x[0] * x[0], |x, _, _| 3.0 * x[0] * x[0]);
+    let x = new_var(2.0);
+    let y = Var::apply(cube(), &[&x]);
+    let dy = grad(&y, &[&x]).remove(0);
+    assert_eq!(dy.value(), 12.0);
+    assert_eq!(grad(&dy, &[&x])[0].value(), 0.0);
+
+    // built from Var ops instead, the exact second derivative 6x comes out
+    let y = &x * &x * &x;
+    let dy = grad(&y, &[&x]).remove(0);
+    assert_eq!(grad(&dy, &[&x])[0].value(), 12.0);
+}
+
+#[cfg(test)]
+fn check_unary(op: &dyn Operation, points: &[f64]) {
+    let eps = 1e-6;
+    for &p in points {
+        let numeric = (op.op(&[p + eps]) - op.op(&[p - eps])) / (2.0 * eps);
+        let analytic = op.grad(&[p], op.op(&[p]), 0);
+        assert!((numeric - analytic).abs() < 1e-6 * (1.0 + analytic.abs()), "at {}: {} vs {}", p, analytic, numeric);
+    }
+}
+
+#[test]
+fn test_transcendental_grads() {
+    check_unary(&ExpOp, &[-2.0, 0.0, 1.5]);
+    check_unary(&LnOp, &[0.1, 1.0, 7.0]);
+    check_unary(&LogOp{ base: 2.0 }, &[0.1, 1.0, 7.0]);
+    check_unary(&LogOp{ base: 10.0 }, &[0.5, 100.0]);
+    check_unary(&SqrtOp, &[0.01, 1.0, 9.0]);
+    check_unary(&SinOp, &[-1.0, 0.0, 2.5]);
+    check_unary(&CosOp, &[-1.0, 0.0, 2.5]);
+    check_unary(&TanOp, &[-1.0, 0.0, 1.2]);
+    check_unary(&AsinOp, &[-0.9, 0.0, 0.5]);
+    check_unary(&AcosOp, &[-0.9, 0.0, 0.5]);
+    check_unary(&AtanOp, &[-3.0, 0.0, 0.5]);
+}
+
+#[test]
+fn test_transcendental_domain() {
+    assert!(LnOp.op(&[-1.0f64]).is_nan());
+    assert_eq!(LnOp.op(&[0.0]), f64::NEG_INFINITY);
+    assert_eq!(LnOp.grad(&[0.0], f64::NEG_INFINITY, 0), f64::INFINITY);
+    assert!(SqrtOp.op(&[-4.0f64]).is_nan());
+    assert_eq!(SqrtOp.grad(&[0.0], 0.0, 0), f64::INFINITY);
+    assert!(AsinOp.op(&[1.5f64]).is_nan());
+    assert!(AcosOp.grad(&[2.0], f64::NAN, 0).is_nan());
+}
+
+#[test]
+fn test_activation_grads() {
+    let points = [-3.0, -0.7, 0.3, 2.0];
+    check_unary(&TanhOp, &points);
+    check_unary(&ReluOp, &points);
+    check_unary(&SigmoidOp, &points);
+    check_unary(&GeluOp, &points);
+    check_unary(&SoftplusOp, &points);
+    check_unary(&LeakyReluOp{ slope: 0.01 }, &points);
+    check_unary(&EluOp{ alpha: 1.0 }, &points);
+    check_unary(&EluOp{ alpha: 0.5 }, &points);
+    check_unary(&SwishOp, &points);
+}
+
+#[test]
+fn test_activation_values() {
+    assert_eq!(ReluOp.op(&[-2.0]), 0.0);
+    assert_eq!(SigmoidOp.op(&[0.0]), 0.5);
+    assert_eq!(LeakyReluOp{ slope: 0.1 }.op(&[-2.0]), -0.2);
+    assert!((GeluOp.op(&[1.0]) - 0.8411919906).abs() < 1e-9);
+    // no overflow for large inputs
+    assert_eq!(SoftplusOp.op(&[1000.0]), 1000.0);
+    assert_eq!(SoftplusOp.op(&[-1000.0]), 0.0);
+    assert!((EluOp{ alpha: 1.0 }.op(&[-50.0]) + 1.0).abs() < 1e-12);
+}
+
@@ -0,0 +1,862 @@
+use crate::{Float, StateDict, StateError, Var};
+
+/// Updates a fixed set of leaf parameters in place from their gradients.
+///
+/// A training step is: build the loss, [`Optimizer::zero_grad`] (or reset the
+/// gradients some other way), `backward`, then [`Optimizer::step`]. Frozen
+/// parameters (see [`Var::set_requires_grad`]) are left alone.
+pub trait Optimizer<T: Float = f64>{
+    /// Applies one update to every parameter that requires a gradient.
+    fn step(&mut self);
+
+    /// The parameters this optimizer updates, in the order it was given them.
+    fn parameters(&self) -> &[Var<T>];
+
+    /// The learning rate the next step will use.
+    fn lr(&self) -> f64;
+
+    /// Changes the learning rate, e.g. from a [`Scheduler`](crate::Scheduler).
+    fn set_lr(&mut self, lr: f64);
+
+    fn zero_grad(&self){
+        for p in self.parameters() { p.grad.set(T::zero()); }
+    }
+
+    /// Everything needed to resume training: the learning rate, step counts
+    /// and per-parameter buffers. Other hyperparameters are configuration and
+    /// are not included.
+    fn state_dict(&self) -> StateDict;
+
+    /// Restores a state saved by [`Optimizer::state_dict`] from an optimizer
+    /// of the same kind over the same number of parameters. On error nothing
+    /// is changed.
+    fn load_state_dict(&mut self, state: &StateDict) -> Result<(), StateError>;
+}
+
+// Implements `Optimizer::lr` and `set_lr` for an optimizer with an `lr` field.
+macro_rules! lr_accessors {
+    () => {
+        fn lr(&self) -> f64{
+            return self.lr;
+        }
+
+        fn set_lr(&mut self, lr: f64){
+            self.lr = lr;
+        }
+    };
+}
+
+fn save<T: Float>(state: &mut StateDict, key: &str, values: &[T]){
+    state.insert(key, values.iter().map(|v| v.to_f64()).collect());
+}
+
+fn read<T: Float>(state: &StateDict, key: &str, len: usize) -> Result<Vec<T>, StateError>{
+    return Ok(state.expect(key, len)?.iter().map(|&v| T::from_f64(v)).collect());
+}
+
+fn save_steps(state: &mut StateDict, steps: &[u64]){
+    state.insert("step", steps.iter().map(|&s| s as f64).collect());
+}
+
+fn read_steps(state: &StateDict, len: usize) -> Result<Vec<u64>, StateError>{
+    return Ok(state.expect("step", len)?.iter().map(|&s| s as u64).collect());
+}
+
+fn read_lr(state: &StateDict) -> Result<f64, StateError>{
+    return Ok(state.expect("lr", 1)?[0]);
+}
+
+/// Stochastic gradient descent with optional momentum, Nesterov momentum,
+/// dampening and L2 weight decay. Configure it with the builder-style setters:
+///
+/// `SGD::new(model.parameters(), 0.1).momentum(0.9).nesterov(true)`
+///
+/// Per step, with `g` the gradient of a parameter `p`:
+///
+/// ```text
+/// g = g + weight_decay * p
+/// buf = g                                          (first step)
+/// buf = momentum * buf + (1 - dampening) * g       (later steps)
+/// g = g + momentum * buf if nesterov, else buf     (when momentum != 0)
+/// p = p - lr * g
+/// ```
+pub struct SGD<T: Float = f64>{
+    params: Vec<Var<T>>,
+    lr: f64,
+    momentum: f64,
+    dampening: f64,
+    weight_decay: f64,
+    nesterov: bool,
+    // one momentum buffer per parameter, created on its first update
+    buffers: Vec<Option<T>>,
+}
+
+impl<T: Float> SGD<T>{
+    pub fn new(params: Vec<Var<T>>, lr: f64) -> SGD<T>{
+        let buffers = vec![None; params.len()];
+        return SGD{ params, lr, momentum: 0.0, dampening: 0.0, weight_decay: 0.0, nesterov: false, buffers };
+    }
+
+    pub fn momentum(mut self, momentum: f64) -> SGD<T>{
+        self.momentum = momentum;
+        return self;
+    }
+
+    pub fn dampening(mut self, dampening: f64) -> SGD<T>{
+        self.dampening = dampening;
+        return self;
+    }
+
+    pub fn weight_decay(mut self, weight_decay: f64) -> SGD<T>{
+        self.weight_decay = weight_decay;
+        return self;
+    }
+
+    /// Nesterov momentum; needs a nonzero momentum and no dampening, which is
+    /// checked on the first step.
+    pub fn nesterov(mut self, nesterov: bool) -> SGD<T>{
+        self.nesterov = nesterov;
+        return self;
+    }
+
+    /// The momentum buffer of the parameter at `index`, if it has one yet.
+    pub fn momentum_buffer(&self, index: usize) -> Option<T>{
+        return self.buffers[index];
+    }
+}
+
+impl<T: Float> Optimizer<T> for SGD<T>{
+    fn step(&mut self){
+        assert!(!self.nesterov || (self.momentum > 0.0 && self.dampening == 0.0),
+            "Nesterov momentum requires a momentum and zero dampening");
+        let (lr, momentum, wd) = (T::from_f64(self.lr), T::from_f64(self.momentum), T::from_f64(self.weight_decay));
+        let keep = T::from_f64(1.0 - self.dampening);
+        for (p, buf) in self.params.iter().zip(self.buffers.iter_mut()) {
+            if !p.requires_grad() { continue; }
+            let mut g = p.grad.get();
+            if self.weight_decay != 0.0 { g += wd * p.value(); }
+            if self.momentum != 0.0 {
+                let b = match *buf {
+                    Some(b) => momentum * b + keep * g,
+                    None => g,
+                };
+                *buf = Some(b);
+                g = if self.nesterov { g + momentum * b } else { b };
+            }
+            p.set_value(p.value() - lr * g);
+        }
+    }
+
+    fn parameters(&self) -> &[Var<T>]{
+        return &self.params;
+    }
+
+    lr_accessors!();
+
+    // `has_momentum_buffer` is 1 for parameters that have one and 0 for those
+    // that do not yet, whose `momentum_buffer` entry is then meaningless
+    fn state_dict(&self) -> StateDict{
+        let mut state = StateDict::new();
+        state.insert("lr", vec![self.lr]);
+        state.insert("has_momentum_buffer", self.buffers.iter().map(|b| if b.is_some() { 1.0 } else { 0.0 }).collect());
+        state.insert("momentum_buffer", self.buffers.iter().map(|b| b.map_or(0.0, |b| b.to_f64())).collect());
+        return state;
+    }
+
+    fn load_state_dict(&mut self, state: &StateDict) -> Result<(), StateError>{
+        let n = self.params.len();
+        let lr = read_lr(state)?;
+        let has = state.expect("has_momentum_buffer", n)?;
+        let buffers = state.expect("momentum_buffer", n)?;
+        self.buffers = has.iter().zip(buffers).map(|(&h, &b)| if h != 0.0 { Some(T::from_f64(b)) } else { None }).collect();
+        self.lr = lr;
+        return Ok(());
+    }
+}
+
+/// Adam, with optional AMSGrad and either L2 weight decay ([`Adam::new`]) or
+/// the decoupled weight decay of AdamW ([`Adam::adamw`]).
+///
+/// Per step `t` of a parameter `p` with gradient `g`:
+///
+/// ```text
+/// g = g + weight_decay * p             (Adam)
+/// p = p - lr * weight_decay * p        (AdamW)
+/// m = beta1 * m + (1 - beta1) * g
+/// v = beta2 * v + (1 - beta2) * g^2
+/// v_max = max(v_max, v)                (AMSGrad, which uses v_max for v below)
+/// p = p - lr * (m / (1 - beta1^t)) / (sqrt(v / (1 - beta2^t)) + eps)
+/// ```
+pub struct Adam<T: Float = f64>{
+    params: Vec<Var<T>>,
+    lr: f64,
+    betas: (f64, f64),
+    eps: f64,
+    weight_decay: f64,
+    decoupled: bool,
+    amsgrad: bool,
+    steps: Vec<u64>,
+    exp_avg: Vec<T>,
+    exp_avg_sq: Vec<T>,
+    max_exp_avg_sq: Vec<T>,
+}
+
+impl<T: Float> Adam<T>{
+    /// Adam with betas (0.9, 0.999), eps 1e-8 and no weight decay.
+    pub fn new(params: Vec<Var<T>>, lr: f64) -> Adam<T>{
+        let n = params.len();
+        return Adam{
+            params,
+            lr,
+            betas: (0.9, 0.999),
+            eps: 1e-8,
+            weight_decay: 0.0,
+            decoupled: false,
+            amsgrad: false,
+            steps: vec![0; n],
+            exp_avg: vec![T::zero(); n],
+            exp_avg_sq: vec![T::zero(); n],
+            max_exp_avg_sq: vec![T::zero(); n],
+        };
+    }
+
+    /// AdamW: weight decay is applied to the parameters directly instead of
+    /// through the gradient. Defaults to a weight decay of 0.01.
+    pub fn adamw(params: Vec<Var<T>>, lr: f64) -> Adam<T>{
+        let mut adam = Adam::new(params, lr);
+        adam.decoupled = true;
+        adam.weight_decay = 0.01;
+        return adam;
+    }
+
+    pub fn betas(mut self, beta1: f64, beta2: f64) -> Adam<T>{
+        self.betas = (beta1, beta2);
+        return self;
+    }
+
+    pub fn eps(mut self, eps: f64) -> Adam<T>{
+        self.eps = eps;
+        return self;
+    }
+
+    pub fn weight_decay(mut self, weight_decay: f64) -> Adam<T>{
+        self.weight_decay = weight_decay;
+        return self;
+    }
+
+    pub fn amsgrad(mut self, amsgrad: bool) -> Adam<T>{
+        self.amsgrad = amsgrad;
+        return self;
+    }
+}
+
+impl<T: Float> Optimizer<T> for Adam<T>{
+    fn step(&mut self){
+        let (b1, b2) = (T::from_f64(self.betas.0), T::from_f64(self.betas.1));
+        let (lr, eps, wd) = (T::from_f64(self.lr), T::from_f64(self.eps), T::from_f64(self.weight_decay));
+        for (k, p) in self.params.iter().enumerate() {
+            if !p.requires_grad() { continue; }
+            let mut g = p.grad.get();
+            if self.decoupled {
+                p.set_value(p.value() - lr * wd * p.value());
+            } else if self.weight_decay != 0.0 {
+                g += wd * p.value();
+            }
+            self.steps[k] += 1;
+            let t = self.steps[k] as i32;
+            let m = b1 * self.exp_avg[k] + (T::one() - b1) * g;
+            let v = b2 * self.exp_avg_sq[k] + (T::one() - b2) * g * g;
+            self.exp_avg[k] = m;
+            self.exp_avg_sq[k] = v;
+            let v = if self.amsgrad {
+                self.max_exp_avg_sq[k] = self.max_exp_avg_sq[k].max(v);
+                self.max_exp_avg_sq[k]
+            } else {
+                v
+            };
+            let bias1 = T::one() - T::from_f64(self.betas.0.powi(t));
+            let bias2 = T::one() - T::from_f64(self.betas.1.powi(t));
+            p.set_value(p.value() - lr * (m / bias1) / ((v / bias2).sqrt() + eps));
+        }
+    }
+
+    fn parameters(&self) -> &[Var<T>]{
+        return &self.params;
+    }
+
+    lr_accessors!();
+
+    fn state_dict(&self) -> StateDict{
+        let mut state = StateDict::new();
+        state.insert("lr", vec![self.lr]);
+        save_steps(&mut state, &self.steps);
+        save(&mut state, "exp_avg", &self.exp_avg);
+        save(&mut state, "exp_avg_sq", &self.exp_avg_sq);
+        if self.amsgrad { save(&mut state, "max_exp_avg_sq", &self.max_exp_avg_sq); }
+        return state;
+    }
+
+    fn load_state_dict(&mut self, state: &StateDict) -> Result<(), StateError>{
+        let n = self.params.len();
+        let lr = read_lr(state)?;
+        let steps = read_steps(state, n)?;
+        let exp_avg = read(state, "exp_avg", n)?;
+        let exp_avg_sq = read(state, "exp_avg_sq", n)?;
+        if self.amsgrad { self.max_exp_avg_sq = read(state, "max_exp_avg_sq", n)?; }
+        self.lr = lr;
+        self.steps = steps;
+        self.exp_avg = exp_avg;
+        self.exp_avg_sq = exp_avg_sq;
+        return Ok(());
+    }
+}
+
+/// RMSProp, with optional momentum, centering and L2 weight decay.
+///
+/// ```text
+/// g = g + weight_decay * p
+/// v = alpha * v + (1 - alpha) * g^2
+/// avg = sqrt(v) + eps, or sqrt(v - mean^2) + eps when centered, with
+///       mean = alpha * mean + (1 - alpha) * g
+/// buf = momentum * buf + g / avg, p = p - lr * buf   (momentum != 0)
+/// p = p - lr * g / avg                              (otherwise)
+/// ```
+pub struct RMSProp<T: Float = f64>{
+    params: Vec<Var<T>>,
+    lr: f64,
+    alpha: f64,
+    eps: f64,
+    weight_decay: f64,
+    momentum: f64,
+    centered: bool,
+    square_avg: Vec<T>,
+    grad_avg: Vec<T>,
+    momentum_buffer: Vec<T>,
+}
+
+impl<T: Float> RMSProp<T>{
+    /// RMSProp with alpha 0.99, eps 1e-8, no momentum and no weight decay.
+    pub fn new(params: Vec<Var<T>>, lr: f64) -> RMSProp<T>{
+        let n = params.len();
+        return RMSProp{
+            params,
+            lr,
+            alpha: 0.99,
+            eps: 1e-8,
+            weight_decay: 0.0,
+            momentum: 0.0,
+            centered: false,
+            square_avg: vec![T::zero(); n],
+            grad_avg: vec![T::zero(); n],
+            momentum_buffer: vec![T::zero(); n],
+        };
+    }
+
+    pub fn alpha(mut self, alpha: f64) -> RMSProp<T>{
+        self.alpha = alpha;
+        return self;
+    }
+
+    pub fn eps(mut self, eps: f64) -> RMSProp<T>{
+        self.eps = eps;
+        return self;
+    }
+
+    pub fn weight_decay(mut self, weight_decay: f64) -> RMSProp<T>{
+        self.weight_decay = weight_decay;
+        return self;
+    }
+
+    pub fn momentum(mut self, momentum: f64) -> RMSProp<T>{
+        self.momentum = momentum;
+        return self;
+    }
+
+    /// Normalizes by an estimate of the variance of the gradient rather than
+    /// its second moment.
+    pub fn centered(mut self, centered: bool) -> RMSProp<T>{
+        self.centered = centered;
+        return self;
+    }
+}
+
+impl<T: Float> Optimizer<T> for RMSProp<T>{
+    fn step(&mut self){
+        let alpha = T::from_f64(self.alpha);
+        let (lr, eps, wd, momentum) = (T::from_f64(self.lr), T::from_f64(self.eps), T::from_f64(self.weight_decay), T::from_f64(self.momentum));
+        for (k, p) in self.params.iter().enumerate() {
+            if !p.requires_grad() { continue; }
+            let mut g = p.grad.get();
+            if self.weight_decay != 0.0 { g += wd * p.value(); }
+            let v = alpha * self.square_avg[k] + (T::one() - alpha) * g * g;
+            self.square_avg[k] = v;
+            let avg = if self.centered {
+                let mean = alpha * self.grad_avg[k] + (T::one() - alpha) * g;
+                self.grad_avg[k] = mean;
+                (v - mean * mean).sqrt() + eps
+            } else {
+                v.sqrt() + eps
+            };
+            if self.momentum != 0.0 {
+                let buf = momentum * self.momentum_buffer[k] + g / avg;
+                self.momentum_buffer[k] = buf;
+                p.set_value(p.value() - lr * buf);
+            } else {
+                p.set_value(p.value() - lr * g / avg);
+            }
+        }
+    }
+
+    fn parameters(&self) -> &[Var<T>]{
+        return &self.params;
+    }
+
+    lr_accessors!();
+
+    fn state_dict(&self) -> StateDict{
+        let mut state = StateDict::new();
+        state.insert("lr", vec![self.lr]);
+        save(&mut state, "square_avg", &self.square_avg);
+        if self.centered { save(&mut state, "grad_avg", &self.grad_avg); }
+        if self.momentum != 0.0 { save(&mut state, "momentum_buffer", &self.momentum_buffer); }
+        return state;
+    }
+
+    fn load_state_dict(&mut self, state: &StateDict) -> Result<(), StateError>{
+        let n = self.params.len();
+        let lr = read_lr(state)?;
+        let square_avg = read(state, "square_avg", n)?;
+        let grad_avg = if self.centered { read(state, "grad_avg", n)? } else { vec![T::zero(); n] };
+        let momentum_buffer = if self.momentum != 0.0 { read(state, "momentum_buffer", n)? } else { vec![T::zero(); n] };
+        self.lr = lr;
+        self.square_avg = square_avg;
+        self.grad_avg = grad_avg;
+        self.momentum_buffer = momentum_buffer;
+        return Ok(());
+    }
+}
+
+/// Adagrad: each parameter's step is scaled down by the root of its summed
+/// squared gradients.
+///
+/// ```text
+/// g = g + weight_decay * p
+/// sum = sum + g^2
+/// p = p - lr / (1 + (t - 1) * lr_decay) * g / (sqrt(sum) + eps)
+/// ```
+pub struct Adagrad<T: Float = f64>{
+    params: Vec<Var<T>>,
+    lr: f64,
+    lr_decay: f64,
+    eps: f64,
+    weight_decay: f64,
+    steps: Vec<u64>,
+    sum: Vec<T>,
+}
+
+impl<T: Float> Adagrad<T>{
+    /// Adagrad with eps 1e-10, no learning-rate decay and no weight decay.
+    pub fn new(params: Vec<Var<T>>, lr: f64) -> Adagrad<T>{
+        let n = params.len();
+        return Adagrad{ params, lr, lr_decay: 0.0, eps: 1e-10, weight_decay: 0.0, steps: vec![0; n], sum: vec![T::zero(); n] };
+    }
+
+    pub fn lr_decay(mut self, lr_decay: f64) -> Adagrad<T>{
+        self.lr_decay = lr_decay;
+        return self;
+    }
+
+    pub fn eps(mut self, eps: f64) -> Adagrad<T>{
+        self.eps = eps;
+        return self;
+    }
+
+    pub fn weight_decay(mut self, weight_decay: f64) -> Adagrad<T>{
+        self.weight_decay = weight_decay;
+        return self;
+    }
+
+    /// Starts every accumulated sum at `value` instead of 0.
+    pub fn initial_accumulator_value(mut self, value: f64) -> Adagrad<T>{
+        self.sum.iter_mut().for_each(|s| *s = T::from_f64(value));
+        return self;
+    }
+}
+
+impl<T: Float> Optimizer<T> for Adagrad<T>{
+    fn step(&mut self){
+        let (eps, wd) = (T::from_f64(self.eps), T::from_f64(self.weight_decay));
+        for (k, p) in self.params.iter().enumerate() {
+            if !p.requires_grad() { continue; }
+            let mut g = p.grad.get();
+            if self.weight_decay != 0.0 { g += wd * p.value(); }
+            self.steps[k] += 1;
+            let lr = T::from_f64(self.lr / (1.0 + (self.steps[k] - 1) as f64 * self.lr_decay));
+            self.sum[k] += g * g;
+            p.set_value(p.value() - lr * g / (self.sum[k].sqrt() + eps));
+        }
+    }
+
+    fn parameters(&self) -> &[Var<T>]{
+        return &self.params;
+    }
+
+    lr_accessors!();
+
+    fn state_dict(&self) -> StateDict{
+        let mut state = StateDict::new();
+        state.insert("lr", vec![self.lr]);
+        save_steps(&mut state, &self.steps);
+        save(&mut state, "sum", &self.sum);
+        return state;
+    }
+
+    fn load_state_dict(&mut self, state: &StateDict) -> Result<(), StateError>{
+        let n = self.params.len();
+        let lr = read_lr(state)?;
+        let steps = read_steps(state, n)?;
+        let sum = read(state, "sum", n)?;
+        self.lr = lr;
+        self.steps = steps;
+        self.sum = sum;
+        return Ok(());
+    }
+}
+
+/// Adadelta: steps are sized by the ratio of running averages of past updates
+/// and past gradients, so `lr` only rescales them.
+///
+/// ```text
+/// g = g + weight_decay * p
+/// v = rho * v + (1 - rho) * g^2
+/// delta = sqrt(u + eps) / sqrt(v + eps) * g
+/// u = rho * u + (1 - rho) * delta^2
+/// p = p - lr * delta
+/// ```
+pub struct Adadelta<T: Float = f64>{
+    params: Vec<Var<T>>,
+    lr: f64,
+    rho: f64,
+    eps: f64,
+    weight_decay: f64,
+    square_avg: Vec<T>,
+    acc_delta: Vec<T>,
+}
+
+impl<T: Float> Adadelta<T>{
+    /// Adadelta with rho 0.9, eps 1e-6 and no weight decay. An `lr` of 1 is
+    /// the usual choice.
+    pub fn new(params: Vec<Var<T>>, lr: f64) -> Adadelta<T>{
+        let n = params.len();
+        return Adadelta{ params, lr, rho: 0.9, eps: 1e-6, weight_decay: 0.0, square_avg: vec![T::zero(); n], acc_delta: vec![T::zero(); n] };
+    }
+
+    pub fn rho(mut self, rho: f64) -> Adadelta<T>{
+        self.rho = rho;
+        return self;
+    }
+
+    pub fn eps(mut self, eps: f64) -> Adadelta<T>{
+        self.eps = eps;
+        return self;
+    }
+
+    pub fn weight_decay(mut self, weight_decay: f64) -> Adadelta<T>{
+        self.weight_decay = weight_decay;
+        return self;
+    }
+}
+
+impl<T: Float> Optimizer<T> for Adadelta<T>{
+    fn step(&mut self){
+        let (lr, rho, eps, wd) = (T::from_f64(self.lr), T::from_f64(self.rho), T::from_f64(self.eps), T::from_f64(self.weight_decay));
+        for (k, p) in self.params.iter().enumerate() {
+            if !p.requires_grad() { continue; }
+            let mut g = p.grad.get();
+            if self.weight_decay != 0.0 { g += wd * p.value(); }
+            let v = rho * self.square_avg[k] + (T::one() - rho) * g * g;
+            self.square_avg[k] = v;
+            let delta = (self.acc_delta[k] + eps).sqrt() / (v + eps).sqrt() * g;
+            self.acc_delta[k] = rho * self.acc_delta[k] + (T::one() - rho) * delta * delta;
+            p.set_value(p.value() - lr * delta);
+        }
+    }
+
+    fn parameters(&self) -> &[Var<T>]{
+        return &self.params;
+    }
+
+    lr_accessors!();
+
+    fn state_dict(&self) -> StateDict{
+        let mut state = StateDict::new();
+        state.insert("lr", vec![self.lr]);
+        save(&mut state, "square_avg", &self.square_avg);
+        save(&mut state, "acc_delta", &self.acc_delta);
+        return state;
+    }
+
+    fn load_state_dict(&mut self, state: &StateDict) -> Result<(), StateError>{
+        let n = self.params.len();
+        let lr = read_lr(state)?;
+        let square_avg = read(state, "square_avg", n)?;
+        let acc_delta = read(state, "acc_delta", n)?;
+        self.lr = lr;
+        self.square_avg = square_avg;
+        self.acc_delta = acc_delta;
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+fn assert_near(a: f64, b: f64) {
+    assert!((a - b).abs() < 1e-12, "{} vs {}", a, b);
+}
+
+#[test]
+fn test_sgd_plain() {
+    let p = crate::new_var(1.0);
+    let mut opt = SGD::new(vec![p.clone()], 0.1);
+    p.grad.set(2.0);
+    opt.step();
+    assert_near(p.value(), 0.8);
+    assert!(opt.momentum_buffer(0).is_none());
+    opt.zero_grad();
+    assert_eq!(p.grad.get(), 0.0);
+}
+
+#[test]
+fn test_sgd_momentum_dampening_decay() {
+    // constant gradient 1, weight decay 0.5, momentum 0.9, dampening 0.1
+    let p = crate::new_var(2.0);
+    let mut opt = SGD::new(vec![p.clone()], 0.1).momentum(0.9).dampening(0.1).weight_decay(0.5);
+    p.grad.set(1.0);
+    opt.step();
+    // g = 1 + 0.5 * 2 = 2, buf = 2, p = 2 - 0.2
+    assert_near(p.value(), 1.8);
+    opt.step();
+    // g = 1 + 0.9 = 1.9, buf = 0.9 * 2 + 0.9 * 1.9 = 3.51
+    assert_near(opt.momentum_buffer(0).unwrap(), 3.51);
+    assert_near(p.value(), 1.8 - 0.351);
+}
+
+#[test]
+fn test_sgd_nesterov() {
+    let p = crate::new_var(0.0);
+    let mut opt = SGD::new(vec![p.clone()], 1.0).momentum(0.5).nesterov(true);
+    p.grad.set(1.0);
+    opt.step();
+    // buf = 1, step = 1 + 0.5
+    assert_near(p.value(), -1.5);
+    opt.step();
+    // buf = 1.5, step = 1 + 0.75
+    assert_near(p.value(), -3.25);
+}
+
+#[test]
+#[should_panic(expected = "Nesterov")]
+fn test_sgd_nesterov_needs_momentum() {
+    let mut opt: SGD = SGD::new(vec![crate::new_var(0.0)], 1.0).nesterov(true);
+    opt.step();
+}
+
+#[test]
+fn test_sgd_skips_frozen_and_converges() {
+    // minimize (a - 3)^2 + (b + 1)^2 with b frozen
+    let a = crate::new_var(0.0);
+    let b = crate::new_var(0.0);
+    b.set_requires_grad(false);
+    let mut opt = SGD::new(vec![a.clone(), b.clone()], 0.1).momentum(0.5);
+    for _ in 0..200 {
+        let loss = (&a - 3.0).pow(2.0) + (&b + 1.0).pow(2.0);
+        opt.zero_grad();
+        loss.backward();
+        opt.step();
+    }
+    assert!((a.value() - 3.0).abs() < 1e-6);
+    assert_eq!(b.value(), 0.0);
+    assert!(opt.momentum_buffer(1).is_none());
+}
+
+#[test]
+fn test_adam_first_steps() {
+    // the first bias-corrected Adam step has size lr whatever the gradient
+    let p = crate::new_var(1.0);
+    let mut opt = Adam::new(vec![p.clone()], 0.1).eps(0.0);
+    p.grad.set(-40.0);
+    opt.step();
+    assert_near(p.value(), 1.1);
+
+    // second step with gradient 1 after -40, computed by hand
+    p.grad.set(1.0);
+    opt.step();
+    let m = 0.9 * 0.1 * -40.0 + 0.1 * 1.0;
+    let v = 0.999 * 0.001 * 1600.0 + 0.001 * 1.0;
+    let expected = 1.1 - 0.1 * (m / (1.0 - 0.81)) / (v / (1.0 - 0.999f64.powi(2))).sqrt();
+    assert_near(p.value(), expected);
+}
+
+#[test]
+fn test_adam_amsgrad_and_decay() {
+    // AMSGrad keeps the largest second moment, so a small gradient after a
+    // large one takes a smaller step than with plain Adam
+    let steps = |amsgrad: bool| {
+        let p = crate::new_var(0.0);
+        let mut opt = Adam::new(vec![p.clone()], 0.1).amsgrad(amsgrad);
+        for g in [10.0, 0.1, 0.1, 0.1] {
+            let before = p.value();
+            p.grad.set(g);
+            opt.step();
+            if g == 0.1 { assert!(p.value() < before); }
+        }
+        return p.value();
+    };
+    assert!(steps(true) > steps(false));
+
+    // AdamW shrinks the parameter even with a zero gradient; L2 Adam's first
+    // step is a plain lr-sized step against the decayed gradient
+    let p = crate::new_var(2.0);
+    let mut opt = Adam::adamw(vec![p.clone()], 0.1).weight_decay(0.5);
+    opt.step();
+    assert_near(p.value(), 2.0 * (1.0 - 0.05));
+    let q = crate::new_var(2.0);
+    let mut opt = Adam::new(vec![q.clone()], 0.1).weight_decay(0.5);
+    opt.step();
+    assert!((q.value() - 1.9).abs() < 1e-6);
+}
+
+#[test]
+fn test_rmsprop_adagrad_adadelta_steps() {
+    let p = crate::new_var(1.0);
+    let mut opt = RMSProp::new(vec![p.clone()], 0.01).eps(0.0);
+    p.grad.set(2.0);
+    opt.step();
+    // v = 0.01 * 4, step = 0.01 * 2 / 0.2
+    assert_near(p.value(), 1.0 - 0.1);
+
+    let p = crate::new_var(1.0);
+    let mut opt = RMSProp::new(vec![p.clone()], 0.01).eps(0.0).centered(true).momentum(0.5);
+    p.grad.set(2.0);
+    opt.step();
+    opt.step();
+    // v = 0.0796, mean = 0.0398 after two steps; buf = 0.5 * 2 / sqrt(0.04 - 0.0004) + ...
+    let first = 2.0 / (0.04f64 - 0.0004).sqrt();
+    let second = 2.0 / (0.0796f64 - 0.0398 * 0.0398).sqrt();
+    assert_near(p.value(), 1.0 - 0.01 * first - 0.01 * (0.5 * first + second));
+
+    let p = crate::new_var(1.0);
+    let mut opt = Adagrad::new(vec![p.clone()], 0.5).eps(0.0).lr_decay(1.0);
+    p.grad.set(3.0);
+    opt.step();
+    opt.step();
+    // sums 9 then 18; the second step uses lr / 2
+    assert_near(p.value(), 1.0 - 0.5 - 0.25 * 3.0 / 18f64.sqrt());
+
+    let p = crate::new_var(1.0);
+    let mut opt = Adadelta::new(vec![p.clone()], 1.0).rho(0.5).eps(1e-4);
+    p.grad.set(1.0);
+    opt.step();
+    // v = 0.5, delta = sqrt(1e-4) / sqrt(0.5001)
+    assert_near(p.value(), 1.0 - 0.01 / 0.5001f64.sqrt());
+}
+
+#[test]
+fn test_adaptive_optimizers_converge() {
+    // minimize (a - 3)^2 + 10 (b + 1)^2
+    let run = |make: &dyn Fn(Vec<Var>) -> Box<dyn Optimizer>, steps: usize| {
+        let a = crate::new_var(0.0);
+        let b = crate::new_var(0.0);
+        let mut opt = make(vec![a.clone(), b.clone()]);
+        for _ in 0..steps {
+            let loss = (&a - 3.0).pow(2.0) + (&b + 1.0).pow(2.0) * 10.0;
+            opt.zero_grad();
+            loss.backward();
+            opt.step();
+        }
+        return (a.value(), b.value());
+    };
+    type Make = Box<dyn Fn(Vec<Var>) -> Box<dyn Optimizer>>;
+    let cases: Vec<(&str, Make, usize)> = vec![
+        ("adam", Box::new(|p| Box::new(Adam::new(p, 0.05))), 2000),
+        ("amsgrad", Box::new(|p| Box::new(Adam::new(p, 0.05).amsgrad(true))), 2000),
+        ("adamw", Box::new(|p| Box::new(Adam::adamw(p, 0.05).weight_decay(0.0))), 2000),
+        ("rmsprop", Box::new(|p| Box::new(RMSProp::new(p, 0.01).momentum(0.5).centered(true))), 2000),
+        ("adagrad", Box::new(|p| Box::new(Adagrad::new(p, 0.5))), 2000),
+        ("adadelta", Box::new(|p| Box::new(Adadelta::new(p, 10.0))), 5000),
+    ];
+    for (name, make, steps) in cases.iter() {
+        let (a, b) = run(make.as_ref(), *steps);
+        assert!((a - 3.0).abs() < 1e-2 && (b + 1.0).abs() < 1e-2, "{}: {} {}", name, a, b);
+    }
+}
+
+#[test]
+fn test_state_dict_resume() {
+    // ten steps in one go match five steps, a save and load into a fresh
+    // optimizer through the text form, and five more
+    fn check<O: Optimizer>(make: impl Fn(Vec<Var>) -> O) {
+        let loss = |p: &[Var]| (&p[0] * &p[1] - 2.0).pow(2.0) + p[1].sin();
+        let params = || vec![crate::new_var(0.5), crate::new_var(-1.5)];
+        let train = |opt: &mut O, p: &[Var], steps: usize| {
+            for _ in 0..steps {
+                opt.zero_grad();
+                loss(p).backward();
+                opt.step();
+            }
+        };
+
+        let straight = params();
+        let mut opt = make(straight.clone());
+        train(&mut opt, &straight, 10);
+
+        let first = params();
+        let mut opt = make(first.clone());
+        train(&mut opt, &first, 5);
+        let text = opt.state_dict().to_string();
+        let resumed: Vec<Var> = first.iter().map(|p| crate::new_var(p.value())).collect();
+        let mut opt = make(resumed.clone());
+        opt.load_state_dict(&text.parse().unwrap()).unwrap();
+        train(&mut opt, &resumed, 5);
+
+        for (a, b) in straight.iter().zip(&resumed) {
+            assert_eq!(a.value(), b.value(), "{}", text);
+        }
+    }
+    check(|p| SGD::new(p, 0.05).momentum(0.9).nesterov(true));
+    check(|p| Adam::new(p, 0.05).amsgrad(true));
+    check(|p| Adam::adamw(p, 0.05));
+    check(|p| RMSProp::new(p, 0.01).momentum(0.9).centered(true));
+    check(|p| Adagrad::new(p, 0.1).lr_decay(0.01));
+    check(|p| Adadelta::new(p, 1.0));
+}
+
+#[test]
+fn test_load_state_dict_errors() {
+    let p = vec![crate::new_var(1.0), crate::new_var(2.0)];
+    let mut adam = Adam::new(p.clone(), 0.1);
+    p[0].grad.set(1.0);
+    adam.step();
+    let saved = adam.state_dict();
+
+    // a different number of parameters is rejected and leaves the state alone
+    let mut small = Adam::new(vec![crate::new_var(1.0)], 0.3);
+    let err = small.load_state_dict(&saved).unwrap_err();
+    assert_eq!(err, StateError::Length{ key: "step".to_string(), expected: 1, found: 2 });
+    assert_eq!(small.state_dict().get("lr"), Some(&[0.3][..]));
+
+    // so is a state saved without AMSGrad when AMSGrad is on
+    let mut ams = Adam::new(p.clone(), 0.1).amsgrad(true);
+    assert_eq!(ams.load_state_dict(&saved), Err(StateError::Missing("max_exp_avg_sq".to_string())));
+
+    // SGD keeps track of which parameters have a momentum buffer, even one
+    // that has diverged to NaN
+    let mut sgd = SGD::new(p.clone(), 0.1).momentum(0.9);
+    p[1].set_requires_grad(false);
+    p[0].grad.set(f64::NAN);
+    sgd.step();
+    let text = sgd.state_dict().to_string();
+    let mut other = SGD::new(p.clone(), 0.1).momentum(0.9);
+    other.load_state_dict(&text.parse().unwrap()).unwrap();
+    assert!(other.momentum_buffer(0).unwrap().is_nan());
+    assert!(other.momentum_buffer(1).is_none());
+}
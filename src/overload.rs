@@ -0,0 +1,61 @@
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::ops::{AddOP, DivOp, MulOp, NegOp, SubOp};
+use crate::{combine, new_const, Float, Var};
+
+// Scalars become constant leaves owned by the node that uses them, so backward
+// never spends work on them.
+trait IntoVar<T: Float>{
+    fn into_var(self) -> Var<T>;
+}
+
+impl<T: Float> IntoVar<T> for &Var<T>{
+    fn into_var(self) -> Var<T> { return self.clone(); }
+}
+
+impl<T: Float> IntoVar<T> for Var<T>{
+    fn into_var(self) -> Var<T> { return self; }
+}
+
+impl<T: Float> IntoVar<T> for T{
+    fn into_var(self) -> Var<T> { return new_const(self); }
+}
+
+macro_rules! impl_binary {
+    ($trait:ident, $method:ident, $op:expr) => {
+        impl_binary!(@one $trait, $method, $op, [T: Float], T, &Var<T>, &Var<T>);
+        impl_binary!(@one $trait, $method, $op, [T: Float], T, &Var<T>, Var<T>);
+        impl_binary!(@one $trait, $method, $op, [T: Float], T, Var<T>, &Var<T>);
+        impl_binary!(@one $trait, $method, $op, [T: Float], T, Var<T>, Var<T>);
+        impl_binary!(@one $trait, $method, $op, [T: Float], T, &Var<T>, T);
+        impl_binary!(@one $trait, $method, $op, [T: Float], T, Var<T>, T);
+        // a scalar on the left cannot be generic over T, so spell out the float types
+        impl_binary!(@one $trait, $method, $op, [], f64, f64, &Var<f64>);
+        impl_binary!(@one $trait, $method, $op, [], f64, f64, Var<f64>);
+        impl_binary!(@one $trait, $method, $op, [], f32, f32, &Var<f32>);
+        impl_binary!(@one $trait, $method, $op, [], f32, f32, Var<f32>);
+    };
+    (@one $trait:ident, $method:ident, $op:expr, [$($gen:tt)*], $t:ty, $lhs:ty, $rhs:ty) => {
+        impl<$($gen)*> $trait<$rhs> for $lhs{
+            type Output = Var<$t>;
+            fn $method(self, o: $rhs) -> Var<$t> {
+                return combine($op, &[&self.into_var(), &o.into_var()]);
+            }
+        }
+    };
+}
+
+impl_binary!(Add, add, AddOP{});
+impl_binary!(Sub, sub, SubOp{});
+impl_binary!(Mul, mul, MulOp{});
+impl_binary!(Div, div, DivOp{});
+
+impl<T: Float> Neg for &Var<T>{
+    type Output = Var<T>;
+    fn neg(self) -> Var<T> { return combine(NegOp{}, &[self]); }
+}
+
+impl<T: Float> Neg for Var<T>{
+    type Output = Var<T>;
+    fn neg(self) -> Var<T> { return combine(NegOp{}, &[&self]); }
+}
@@ -0,0 +1,683 @@
+use std::f64::consts::PI;
+
+use crate::{Float, Optimizer, StateDict, StateError};
+
+/// Drives a learning rate over the course of training. A scheduler starts at
+/// step 0 and is advanced with [`Scheduler::step`], once per optimizer step or
+/// once per epoch as the caller prefers; hand its rate to the optimizer with
+/// [`Scheduler::apply`] before each optimizer step.
+///
+/// Schedulers are composed with [`Sequential`] (e.g. warmup then cosine) and
+/// saved alongside the optimizer with [`StateDict::insert_scoped`].
+pub trait Scheduler{
+    /// The learning rate for the current step.
+    fn lr(&self) -> f64;
+
+    /// Moves on to the next step.
+    fn step(&mut self);
+
+    /// Reports a metric, such as a validation loss, for schedulers that react
+    /// to one; it is taken into account by the next [`Scheduler::step`]. The
+    /// others ignore it.
+    fn report(&mut self, _metric: f64){}
+
+    fn state_dict(&self) -> StateDict;
+
+    /// Restores a state saved by [`Scheduler::state_dict`] from a scheduler
+    /// with the same configuration. On error nothing is changed.
+    fn load_state_dict(&mut self, state: &StateDict) -> Result<(), StateError>;
+
+    /// Sets the learning rate of `opt` to this scheduler's current one.
+    fn apply<T: Float>(&self, opt: &mut impl Optimizer<T>) where Self: Sized{
+        opt.set_lr(self.lr());
+    }
+}
+
+// lets a boxed scheduler, such as one chosen at run time, use `apply` too
+impl<S: Scheduler + ?Sized> Scheduler for Box<S>{
+    fn lr(&self) -> f64{
+        return (**self).lr();
+    }
+
+    fn step(&mut self){
+        (**self).step();
+    }
+
+    fn report(&mut self, metric: f64){
+        (**self).report(metric);
+    }
+
+    fn state_dict(&self) -> StateDict{
+        return (**self).state_dict();
+    }
+
+    fn load_state_dict(&mut self, state: &StateDict) -> Result<(), StateError>{
+        return (**self).load_state_dict(state);
+    }
+}
+
+fn save_step(step: usize) -> StateDict{
+    let mut state = StateDict::new();
+    state.insert("step", vec![step as f64]);
+    return state;
+}
+
+fn read_step(state: &StateDict) -> Result<usize, StateError>{
+    return Ok(state.expect("step", 1)?[0] as usize);
+}
+
+// Implements the state of a scheduler whose rate depends on its step alone.
+macro_rules! step_state {
+    () => {
+        fn state_dict(&self) -> StateDict{
+            return save_step(self.step);
+        }
+
+        fn load_state_dict(&mut self, state: &StateDict) -> Result<(), StateError>{
+            self.step = read_step(state)?;
+            return Ok(());
+        }
+    };
+}
+
+/// Multiplies the rate by `gamma` every `step_size` steps.
+pub struct StepLR{
+    base_lr: f64,
+    step_size: usize,
+    gamma: f64,
+    step: usize,
+}
+
+impl StepLR{
+    pub fn new(base_lr: f64, step_size: usize, gamma: f64) -> StepLR{
+        assert!(step_size > 0, "StepLR needs a positive step_size");
+        return StepLR{ base_lr, step_size, gamma, step: 0 };
+    }
+}
+
+impl Scheduler for StepLR{
+    fn lr(&self) -> f64{
+        return self.base_lr * self.gamma.powi((self.step / self.step_size) as i32);
+    }
+
+    fn step(&mut self){
+        self.step += 1;
+    }
+
+    step_state!();
+}
+
+/// Multiplies the rate by `gamma` every step.
+pub struct ExponentialLR{
+    base_lr: f64,
+    gamma: f64,
+    step: usize,
+}
+
+impl ExponentialLR{
+    pub fn new(base_lr: f64, gamma: f64) -> ExponentialLR{
+        return ExponentialLR{ base_lr, gamma, step: 0 };
+    }
+}
+
+impl Scheduler for ExponentialLR{
+    fn lr(&self) -> f64{
+        return self.base_lr * self.gamma.powi(self.step as i32);
+    }
+
+    fn step(&mut self){
+        self.step += 1;
+    }
+
+    step_state!();
+}
+
+// From `start` at pct 0 to `end` at pct 1 along half a cosine.
+fn cosine(start: f64, end: f64, pct: f64) -> f64{
+    return end + (start - end) * (1.0 + (PI * pct).cos()) / 2.0;
+}
+
+/// Cosine annealing with warm restarts (SGDR): the rate follows half a cosine
+/// from `base_lr` down to `eta_min` over `t_0` steps, then jumps back up and
+/// does it again, each cycle `t_mult` times as long as the one before.
+pub struct CosineAnnealingWarmRestarts{
+    base_lr: f64,
+    t_0: usize,
+    t_mult: usize,
+    eta_min: f64,
+    step: usize,
+}
+
+impl CosineAnnealingWarmRestarts{
+    pub fn new(base_lr: f64, t_0: usize, t_mult: usize, eta_min: f64) -> CosineAnnealingWarmRestarts{
+        assert!(t_0 > 0 && t_mult > 0, "CosineAnnealingWarmRestarts needs positive t_0 and t_mult");
+        return CosineAnnealingWarmRestarts{ base_lr, t_0, t_mult, eta_min, step: 0 };
+    }
+
+    // position within the current cycle and that cycle's length, in closed form
+    // so that lr() costs the same at any step
+    fn cycle(&self) -> (usize, usize){
+        let (step, t_0, m) = (self.step as u128, self.t_0 as u128, self.t_mult as u128);
+        if m == 1 {
+            return (self.step % self.t_0, self.t_0);
+        }
+        // cycle n starts at t_0 (m^n - 1) / (m - 1); estimate n with a log, then
+        // correct for rounding
+        let start = |n: u32| m.checked_pow(n).map_or(u128::MAX, |p| t_0.saturating_mul(p - 1) / (m - 1));
+        let estimate = ((step * (m - 1)) as f64 / t_0 as f64 + 1.0).log(m as f64).floor();
+        let mut n = estimate.max(0.0) as u32;
+        while n > 0 && start(n) > step { n -= 1; }
+        while start(n + 1) <= step { n += 1; }
+        return ((step - start(n)) as usize, (t_0 * m.pow(n)) as usize);
+    }
+}
+
+impl Scheduler for CosineAnnealingWarmRestarts{
+    fn lr(&self) -> f64{
+        let (t, len) = self.cycle();
+        return cosine(self.base_lr, self.eta_min, t as f64 / len as f64);
+    }
+
+    fn step(&mut self){
+        self.step += 1;
+    }
+
+    step_state!();
+}
+
+/// Ramps the rate linearly from `start_factor * base_lr` up to `base_lr` over
+/// `warmup_steps`, then holds it; put it first in a [`Sequential`] to warm up
+/// another schedule.
+pub struct LinearWarmup{
+    base_lr: f64,
+    start_factor: f64,
+    warmup_steps: usize,
+    step: usize,
+}
+
+impl LinearWarmup{
+    pub fn new(base_lr: f64, start_factor: f64, warmup_steps: usize) -> LinearWarmup{
+        return LinearWarmup{ base_lr, start_factor, warmup_steps, step: 0 };
+    }
+}
+
+impl Scheduler for LinearWarmup{
+    fn lr(&self) -> f64{
+        if self.step >= self.warmup_steps { return self.base_lr; }
+        let pct = self.step as f64 / self.warmup_steps as f64;
+        return self.base_lr * (self.start_factor + (1.0 - self.start_factor) * pct);
+    }
+
+    fn step(&mut self){
+        self.step += 1;
+    }
+
+    step_state!();
+}
+
+/// The one-cycle policy: over the first `pct_start` of `total_steps` the rate
+/// rises from `max_lr / div_factor` to `max_lr`, then falls to
+/// `max_lr / (div_factor * final_div_factor)` at the last step, both along
+/// half a cosine. Past `total_steps` it stays at the final rate.
+pub struct OneCycle{
+    max_lr: f64,
+    total_steps: usize,
+    pct_start: f64,
+    div_factor: f64,
+    final_div_factor: f64,
+    step: usize,
+}
+
+impl OneCycle{
+    /// A cycle with `pct_start` 0.3, `div_factor` 25 and `final_div_factor` 1e4.
+    pub fn new(max_lr: f64, total_steps: usize) -> OneCycle{
+        // one step each to start, peak and finish
+        assert!(total_steps >= 3, "OneCycle needs at least three steps");
+        return OneCycle{ max_lr, total_steps, pct_start: 0.3, div_factor: 25.0, final_div_factor: 1e4, step: 0 };
+    }
+
+    pub fn pct_start(mut self, pct_start: f64) -> OneCycle{
+        self.pct_start = pct_start;
+        return self;
+    }
+
+    pub fn div_factor(mut self, div_factor: f64) -> OneCycle{
+        self.div_factor = div_factor;
+        return self;
+    }
+
+    pub fn final_div_factor(mut self, final_div_factor: f64) -> OneCycle{
+        self.final_div_factor = final_div_factor;
+        return self;
+    }
+}
+
+impl Scheduler for OneCycle{
+    fn lr(&self) -> f64{
+        let initial = self.max_lr / self.div_factor;
+        let last = (self.total_steps - 1) as f64;
+        // kept strictly inside the cycle so that very short cycles still both
+        // ramp up and anneal
+        let peak = (self.pct_start * self.total_steps as f64 - 1.0).clamp(1.0, last - 1.0);
+        let t = (self.step as f64).min(last);
+        if t <= peak {
+            return cosine(initial, self.max_lr, t / peak);
+        }
+        return cosine(self.max_lr, initial / self.final_div_factor, (t - peak) / (last - peak));
+    }
+
+    fn step(&mut self){
+        self.step += 1;
+    }
+
+    step_state!();
+}
+
+/// Whether a lower or a higher reported metric is better.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlateauMode{
+    Min,
+    Max,
+}
+
+/// Multiplies the rate by `factor` once the reported metric has not improved
+/// for more than `patience` steps, then waits `cooldown` steps before watching
+/// again. An improvement must beat the best value so far by the relative
+/// `threshold`. The rate never goes below `min_lr`.
+///
+/// Report the metric with [`Scheduler::report`] before each step; steps with
+/// no new report leave everything unchanged.
+pub struct ReduceOnPlateau{
+    lr: f64,
+    mode: PlateauMode,
+    factor: f64,
+    patience: usize,
+    threshold: f64,
+    cooldown: usize,
+    min_lr: f64,
+    best: f64,
+    bad_steps: usize,
+    cooldown_left: usize,
+    pending: Option<f64>,
+}
+
+impl ReduceOnPlateau{
+    /// Watches for a decreasing metric with `factor` 0.1, `patience` 10,
+    /// `threshold` 1e-4 and no cooldown or minimum rate.
+    pub fn new(lr: f64) -> ReduceOnPlateau{
+        return ReduceOnPlateau{
+            lr,
+            mode: PlateauMode::Min,
+            factor: 0.1,
+            patience: 10,
+            threshold: 1e-4,
+            cooldown: 0,
+            min_lr: 0.0,
+            best: f64::INFINITY,
+            bad_steps: 0,
+            cooldown_left: 0,
+            pending: None,
+        };
+    }
+
+    pub fn mode(mut self, mode: PlateauMode) -> ReduceOnPlateau{
+        self.mode = mode;
+        self.best = match mode {
+            PlateauMode::Min => f64::INFINITY,
+            PlateauMode::Max => f64::NEG_INFINITY,
+        };
+        return self;
+    }
+
+    pub fn factor(mut self, factor: f64) -> ReduceOnPlateau{
+        assert!(factor < 1.0, "ReduceOnPlateau needs a factor below 1");
+        self.factor = factor;
+        return self;
+    }
+
+    pub fn patience(mut self, patience: usize) -> ReduceOnPlateau{
+        self.patience = patience;
+        return self;
+    }
+
+    pub fn threshold(mut self, threshold: f64) -> ReduceOnPlateau{
+        self.threshold = threshold;
+        return self;
+    }
+
+    pub fn cooldown(mut self, cooldown: usize) -> ReduceOnPlateau{
+        self.cooldown = cooldown;
+        return self;
+    }
+
+    pub fn min_lr(mut self, min_lr: f64) -> ReduceOnPlateau{
+        self.min_lr = min_lr;
+        return self;
+    }
+
+    fn improves(&self, metric: f64) -> bool{
+        // before the first report the best is infinite and anything improves
+        let margin = if self.best.is_finite() { self.best.abs() * self.threshold } else { 0.0 };
+        return match self.mode {
+            PlateauMode::Min => metric < self.best - margin,
+            PlateauMode::Max => metric > self.best + margin,
+        };
+    }
+}
+
+impl Scheduler for ReduceOnPlateau{
+    fn lr(&self) -> f64{
+        return self.lr;
+    }
+
+    fn report(&mut self, metric: f64){
+        self.pending = Some(metric);
+    }
+
+    fn step(&mut self){
+        let metric = match self.pending.take() {
+            Some(metric) => metric,
+            None => return,
+        };
+        if self.improves(metric) {
+            self.best = metric;
+            self.bad_steps = 0;
+        } else {
+            self.bad_steps += 1;
+        }
+        if self.cooldown_left > 0 {
+            self.cooldown_left -= 1;
+            // bad steps during the cooldown do not count
+            self.bad_steps = 0;
+        }
+        if self.bad_steps > self.patience {
+            self.lr = (self.lr * self.factor).max(self.min_lr);
+            self.cooldown_left = self.cooldown;
+            self.bad_steps = 0;
+        }
+    }
+
+    fn state_dict(&self) -> StateDict{
+        let mut state = StateDict::new();
+        state.insert("lr", vec![self.lr]);
+        state.insert("best", vec![self.best]);
+        state.insert("bad_steps", vec![self.bad_steps as f64]);
+        state.insert("cooldown_left", vec![self.cooldown_left as f64]);
+        return state;
+    }
+
+    fn load_state_dict(&mut self, state: &StateDict) -> Result<(), StateError>{
+        let lr = state.expect("lr", 1)?[0];
+        let best = state.expect("best", 1)?[0];
+        let bad_steps = state.expect("bad_steps", 1)?[0] as usize;
+        let cooldown_left = state.expect("cooldown_left", 1)?[0] as usize;
+        self.lr = lr;
+        self.best = best;
+        self.bad_steps = bad_steps;
+        self.cooldown_left = cooldown_left;
+        self.pending = None;
+        return Ok(());
+    }
+}
+
+/// Runs schedulers one after the other: the first until step `milestones[0]`,
+/// the second (starting from its own step 0) until `milestones[1]`, and so on.
+pub struct Sequential{
+    schedulers: Vec<Box<dyn Scheduler>>,
+    milestones: Vec<usize>,
+    step: usize,
+}
+
+impl Sequential{
+    /// Panics unless there is one milestone fewer than schedulers and the
+    /// milestones are increasing.
+    pub fn new(schedulers: Vec<Box<dyn Scheduler>>, milestones: Vec<usize>) -> Sequential{
+        assert!(!schedulers.is_empty() && milestones.len() + 1 == schedulers.len(),
+            "Sequential needs one milestone fewer than schedulers");
+        assert!(milestones.windows(2).all(|w| w[0] < w[1]), "Sequential needs increasing milestones");
+        return Sequential{ schedulers, milestones, step: 0 };
+    }
+
+    fn active(&self) -> usize{
+        return self.milestones.iter().take_while(|&&m| m <= self.step).count();
+    }
+}
+
+impl Scheduler for Sequential{
+    fn lr(&self) -> f64{
+        return self.schedulers[self.active()].lr();
+    }
+
+    fn step(&mut self){
+        let before = self.active();
+        self.step += 1;
+        // a scheduler taking over starts at its own step 0
+        if self.active() == before { self.schedulers[before].step(); }
+    }
+
+    fn report(&mut self, metric: f64){
+        let k = self.active();
+        self.schedulers[k].report(metric);
+    }
+
+    fn state_dict(&self) -> StateDict{
+        let mut state = save_step(self.step);
+        for (k, s) in self.schedulers.iter().enumerate() {
+            state.insert_scoped(&k.to_string(), &s.state_dict());
+        }
+        return state;
+    }
+
+    // on error, the parts loaded so far are put back, so nothing changes
+    fn load_state_dict(&mut self, state: &StateDict) -> Result<(), StateError>{
+        let step = read_step(state)?;
+        let backup: Vec<StateDict> = self.schedulers.iter().map(|s| s.state_dict()).collect();
+        for k in 0..self.schedulers.len() {
+            if let Err(e) = self.schedulers[k].load_state_dict(&state.scoped(&k.to_string())) {
+                for (s, b) in self.schedulers.iter_mut().zip(&backup).take(k) {
+                    s.load_state_dict(b).expect("restoring a saved scheduler state");
+                }
+                return Err(e);
+            }
+        }
+        self.step = step;
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+fn lrs(s: &mut dyn Scheduler, steps: usize) -> Vec<f64> {
+    let mut out = Vec::new();
+    for _ in 0..steps {
+        out.push(s.lr());
+        s.step();
+    }
+    return out;
+}
+
+#[cfg(test)]
+fn assert_all_near(a: &[f64], b: &[f64]) {
+    assert_eq!(a.len(), b.len());
+    for (x, y) in a.iter().zip(b) {
+        assert!((x - y).abs() < 1e-12, "{:?} vs {:?}", a, b);
+    }
+}
+
+#[test]
+fn test_step_and_exponential() {
+    assert_all_near(&lrs(&mut StepLR::new(1.0, 2, 0.5), 5), &[1.0, 1.0, 0.5, 0.5, 0.25]);
+    assert_all_near(&lrs(&mut ExponentialLR::new(2.0, 0.1), 3), &[2.0, 0.2, 0.02]);
+}
+
+#[test]
+fn test_cosine_warm_restarts() {
+    // cycles of 2 then 4 steps between 1 and 0
+    let got = lrs(&mut CosineAnnealingWarmRestarts::new(1.0, 2, 2, 0.0), 7);
+    let q = (1.0 + (PI / 4.0).cos()) / 2.0;
+    assert_all_near(&got, &[1.0, 0.5, 1.0, q, 0.5, 1.0 - q, 1.0]);
+
+    let floor = lrs(&mut CosineAnnealingWarmRestarts::new(0.5, 4, 1, 0.1), 5);
+    assert_all_near(&floor, &[0.5, 0.1 + 0.4 * (1.0 + (PI / 4.0).cos()) / 2.0, 0.3, 0.1 + 0.4 * (1.0 - (PI / 4.0).cos()) / 2.0, 0.5]);
+}
+
+#[test]
+fn test_cosine_warm_restarts_far_out() {
+    // cycles of 1, 3, 9, ... start at (3^n - 1) / 2
+    let mut s = CosineAnnealingWarmRestarts::new(1.0, 1, 3, 0.0);
+    for (step, expected) in [(0, (0, 1)), (1, (0, 3)), (3, (2, 3)), (4, (0, 9)), (12, (8, 9)), (13, (0, 27))] {
+        s.step = step;
+        assert_eq!(s.cycle(), expected, "step {}", step);
+    }
+    let start = (3usize.pow(30) - 1) / 2;
+    s.step = start + 5;
+    assert_eq!(s.cycle(), (5, 3usize.pow(30)));
+    s.step = start - 1;
+    assert_eq!(s.cycle(), (3usize.pow(29) - 1, 3usize.pow(29)));
+
+    // with t_mult 1 a far step costs no more than an early one
+    let mut s = CosineAnnealingWarmRestarts::new(1.0, 4, 1, 0.0);
+    s.step = 300_000_002;
+    assert_eq!(s.cycle(), (2, 4));
+    assert!((s.lr() - 0.5).abs() < 1e-12);
+}
+
+#[test]
+fn test_linear_warmup_then_cosine() {
+    let warmup = Box::new(LinearWarmup::new(1.0, 0.25, 3));
+    let cosine = Box::new(CosineAnnealingWarmRestarts::new(1.0, 2, 1, 0.0));
+    let mut s = Sequential::new(vec![warmup, cosine], vec![3]);
+    assert_all_near(&lrs(&mut s, 7), &[0.25, 0.5, 0.75, 1.0, 0.5, 1.0, 0.5]);
+}
+
+#[test]
+fn test_one_cycle() {
+    let mut s = OneCycle::new(1.0, 11).pct_start(0.5).div_factor(10.0).final_div_factor(100.0);
+    let got = lrs(&mut s, 13);
+    // rises over 4.5 steps from 0.1 to 1, then falls to 0.001 at step 10
+    assert!((got[0] - 0.1).abs() < 1e-12);
+    assert!(got[..5].windows(2).all(|w| w[0] < w[1]));
+    assert!((got[4] - cosine(0.1, 1.0, 4.0 / 4.5)).abs() < 1e-12);
+    assert!(got[5..11].windows(2).all(|w| w[0] > w[1]));
+    assert!((got[10] - 0.001).abs() < 1e-12);
+    assert_eq!(got[12], got[10]);
+
+    // the shortest cycle still starts low, peaks and ends at the final rate,
+    // even when pct_start would put the peak at an end
+    for pct in [0.0, 0.5, 1.0] {
+        let got = lrs(&mut OneCycle::new(1.0, 3).pct_start(pct).div_factor(10.0).final_div_factor(10.0), 3);
+        assert_all_near(&got, &[0.1, 1.0, 0.01]);
+    }
+    assert!(std::panic::catch_unwind(|| OneCycle::new(1.0, 2)).is_err());
+}
+
+#[test]
+fn test_reduce_on_plateau() {
+    let mut s = ReduceOnPlateau::new(1.0).patience(1).cooldown(1).factor(0.5).min_lr(0.2);
+    let mut got = Vec::new();
+    for metric in [5.0, 4.0, 4.0, 4.0, 4.0, 4.0, 4.0, 3.0, 3.0, 3.0, 3.0, 3.0, 3.0, 3.0] {
+        s.report(metric);
+        s.step();
+        got.push(s.lr());
+    }
+    // two bad steps reduce; the cooldown step after a reduction does not count
+    assert_all_near(&got, &[1.0, 1.0, 1.0, 0.5, 0.5, 0.5, 0.25, 0.25, 0.25, 0.2, 0.2, 0.2, 0.2, 0.2]);
+
+    // steps without a report change nothing; Max mode follows increases
+    let mut s = ReduceOnPlateau::new(1.0).mode(PlateauMode::Max).patience(0);
+    s.step();
+    assert_eq!(s.lr(), 1.0);
+    s.report(1.0);
+    s.step();
+    s.report(2.0);
+    s.step();
+    assert_eq!(s.lr(), 1.0);
+    s.report(1.5);
+    s.step();
+    assert_eq!(s.lr(), 0.1);
+}
+
+#[test]
+fn test_sequential_report_and_checks() {
+    let warmup = Box::new(LinearWarmup::new(1.0, 0.5, 1));
+    let plateau = Box::new(ReduceOnPlateau::new(1.0).patience(0));
+    let mut s = Sequential::new(vec![warmup, plateau], vec![1]);
+    assert_eq!(s.lr(), 0.5);
+    s.report(0.0);
+    s.step();
+    assert_eq!(s.lr(), 1.0);
+    s.report(1.0);
+    s.step();
+    s.report(1.0);
+    s.step();
+    assert!((s.lr() - 0.1).abs() < 1e-12);
+
+    let bad = std::panic::catch_unwind(|| Sequential::new(vec![Box::new(ExponentialLR::new(1.0, 0.5))], vec![3]));
+    assert!(bad.is_err());
+}
+
+#[test]
+fn test_scheduler_resume_with_optimizer() {
+    use crate::{new_var, Optimizer, Var, SGD};
+
+    let make = || {
+        let warmup = Box::new(LinearWarmup::new(0.1, 0.1, 5));
+        let restarts = Box::new(CosineAnnealingWarmRestarts::new(0.1, 3, 2, 0.001));
+        return Sequential::new(vec![warmup, restarts], vec![5]);
+    };
+    let train = |opt: &mut SGD, sched: &mut Sequential, p: &Var, steps: usize| {
+        for _ in 0..steps {
+            sched.apply(opt);
+            opt.zero_grad();
+            (p * p).sin().backward();
+            opt.step();
+            sched.step();
+        }
+    };
+
+    let p = new_var(1.0);
+    let mut opt = SGD::new(vec![p.clone()], 0.0).momentum(0.9);
+    let mut sched = make();
+    train(&mut opt, &mut sched, &p, 20);
+
+    // save optimizer and scheduler together, through the text form
+    let q = new_var(1.0);
+    let mut opt = SGD::new(vec![q.clone()], 0.0).momentum(0.9);
+    let mut sched = make();
+    train(&mut opt, &mut sched, &q, 8);
+    let mut state = StateDict::new();
+    state.insert_scoped("optimizer", &opt.state_dict());
+    state.insert_scoped("scheduler", &sched.state_dict());
+    let state: StateDict = state.to_string().parse().unwrap();
+
+    let r = new_var(q.value());
+    let mut opt = SGD::new(vec![r.clone()], 0.0).momentum(0.9);
+    let mut sched = make();
+    opt.load_state_dict(&state.scoped("optimizer")).unwrap();
+    sched.load_state_dict(&state.scoped("scheduler")).unwrap();
+    train(&mut opt, &mut sched, &r, 12);
+    assert_eq!(r.value(), p.value());
+
+    // a state for a different composition is rejected without changes
+    let before = sched.state_dict();
+    let mut wrong = state.scoped("scheduler");
+    wrong.insert("1.step", vec![]);
+    assert!(matches!(sched.load_state_dict(&wrong), Err(StateError::Length{ .. })));
+    assert_eq!(sched.state_dict(), before);
+}
+
+#[test]
+fn test_apply_drives_optimizer() {
+    use crate::{new_var, Adam, Optimizer};
+
+    let p = new_var(0.0);
+    let mut opt = Adam::new(vec![p.clone()], 1.0);
+    let mut sched: Box<dyn Scheduler> = Box::new(StepLR::new(0.5, 1, 0.1));
+    let mut seen = Vec::new();
+    for _ in 0..3 {
+        sched.apply(&mut opt);
+        seen.push(opt.lr());
+        opt.step();
+        sched.step();
+    }
+    assert_all_near(&seen, &[0.5, 0.05, 0.005]);
+}
@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Named arrays of numbers describing the internal state of an optimizer (or
+/// anything else that needs to be saved to resume training), e.g. `lr` or
+/// `exp_avg` with one entry per parameter. Values are stored as `f64`
+/// whatever the scalar type of the graph.
+///
+/// The text form, written by `Display` and read by `FromStr`, has one entry per
+/// line: the key followed by its values, separated by spaces. Values are
+/// written so that they read back exactly.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StateDict{
+    entries: BTreeMap<String, Vec<f64>>,
+}
+
+/// Why a [`StateDict`] could not be read or loaded.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StateError{
+    /// An entry the loader needs is absent.
+    Missing(String),
+    /// An entry has the wrong number of values, e.g. because it was saved for a
+    /// different set of parameters.
+    Length{ key: String, expected: usize, found: usize },
+    /// A line of the text form is malformed.
+    Parse{ line: usize, message: String },
+}
+
+impl fmt::Display for StateError{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        return match self {
+            StateError::Missing(key) => write!(f, "missing state entry `{}`", key),
+            StateError::Length{ key, expected, found } => {
+                write!(f, "state entry `{}` has {} values, expected {}", key, found, expected)
+            }
+            StateError::Parse{ line, message } => write!(f, "line {}: {}", line, message),
+        };
+    }
+}
+
+impl std::error::Error for StateError{}
+
+impl StateDict{
+    pub fn new() -> StateDict{
+        return StateDict::default();
+    }
+
+    /// Sets `key`, replacing any previous values. Panics if `key` is empty or
+    /// contains whitespace, which the text form could not represent.
+    pub fn insert(&mut self, key: &str, values: Vec<f64>){
+        assert!(!key.is_empty() && !key.contains(char::is_whitespace), "invalid state key `{}`", key);
+        self.entries.insert(key.to_string(), values);
+    }
+
+    pub fn get(&self, key: &str) -> Option<&[f64]>{
+        return self.entries.get(key).map(|v| v.as_slice());
+    }
+
+    /// The values at `key`, which must have exactly `len` of them.
+    pub fn expect(&self, key: &str, len: usize) -> Result<&[f64], StateError>{
+        let values = self.get(key).ok_or_else(|| StateError::Missing(key.to_string()))?;
+        if values.len() != len {
+            return Err(StateError::Length{ key: key.to_string(), expected: len, found: values.len() });
+        }
+        return Ok(values);
+    }
+
+    /// The keys in sorted order.
+    pub fn keys(&self) -> impl Iterator<Item = &str>{
+        return self.entries.keys().map(|k| k.as_str());
+    }
+
+    pub fn len(&self) -> usize{
+        return self.entries.len();
+    }
+
+    pub fn is_empty(&self) -> bool{
+        return self.entries.is_empty();
+    }
+
+    /// Copies every entry of `other` in under `prefix.`, so that the states of
+    /// several objects (say an optimizer and its scheduler) share one dict.
+    pub fn insert_scoped(&mut self, prefix: &str, other: &StateDict){
+        for (k, v) in other.entries.iter() {
+            self.insert(&format!("{}.{}", prefix, k), v.clone());
+        }
+    }
+
+    /// The entries under `prefix.`, with the prefix removed; the inverse of
+    /// [`StateDict::insert_scoped`].
+    pub fn scoped(&self, prefix: &str) -> StateDict{
+        let start = format!("{}.", prefix);
+        let entries = self.entries.iter()
+            .filter_map(|(k, v)| k.strip_prefix(&start).map(|rest| (rest.to_string(), v.clone())))
+            .collect();
+        return StateDict{ entries };
+    }
+}
+
+impl fmt::Display for StateDict{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        for (k, values) in self.entries.iter() {
+            write!(f, "{}", k)?;
+            // `{:?}` is the shortest form that parses back to the same f64
+            for v in values { write!(f, " {:?}", v)?; }
+            writeln!(f)?;
+        }
+        return Ok(());
+    }
+}
+
+impl FromStr for StateDict{
+    type Err = StateError;
+
+    fn from_str(s: &str) -> Result<StateDict, StateError>{
+        let mut state = StateDict::new();
+        for (i, line) in s.lines().enumerate() {
+            let mut words = line.split_whitespace();
+            let key = match words.next() {
+                Some(key) => key,
+                None => continue,
+            };
+            let values = words.map(|w| w.parse::<f64>().map_err(|e| StateError::Parse{
+                line: i + 1,
+                message: format!("`{}`: {}", w, e),
+            })).collect::<Result<Vec<f64>, StateError>>()?;
+            if state.entries.insert(key.to_string(), values).is_some() {
+                return Err(StateError::Parse{ line: i + 1, message: format!("duplicate key `{}`", key) });
+            }
+        }
+        return Ok(state);
+    }
+}
+
+#[test]
+fn test_state_text_round_trip() {
+    let mut state = StateDict::new();
+    state.insert("lr", vec![0.001]);
+    state.insert("exp_avg", vec![0.1, -1e-300, f64::NAN, 1.0 / 3.0]);
+    state.insert("empty", vec![]);
+    let text = state.to_string();
+    assert_eq!(text.lines().next(), Some("empty"));
+    let back: StateDict = text.parse().unwrap();
+    assert_eq!(back.get("lr"), Some(&[0.001][..]));
+    assert_eq!(back.get("exp_avg").unwrap()[3], 1.0 / 3.0);
+    assert!(back.get("exp_avg").unwrap()[2].is_nan());
+    assert_eq!(back.keys().collect::<Vec<_>>(), ["empty", "exp_avg", "lr"]);
+
+    let mut outer = StateDict::new();
+    outer.insert_scoped("optim", &state);
+    assert_eq!(outer.get("optim.lr"), Some(&[0.001][..]));
+    assert_eq!(outer.scoped("optim").len(), 3);
+    assert!(outer.scoped("opt").is_empty());
+}
+
+#[test]
+fn test_state_errors() {
+    let bad = "lr 0.1\nstep 1 two\n".parse::<StateDict>();
+    assert!(matches!(bad, Err(StateError::Parse{ line: 2, .. })), "{:?}", bad);
+    let dup = "lr 0.1\nlr 0.2".parse::<StateDict>();
+    assert!(matches!(dup, Err(StateError::Parse{ line: 2, .. })));
+
+    let state: StateDict = "lr 0.1\n\nsum 1 2 3\n".parse().unwrap();
+    assert_eq!(state.expect("sum", 3), Ok(&[1.0, 2.0, 3.0][..]));
+    assert_eq!(state.expect("sum", 2), Err(StateError::Length{ key: "sum".to_string(), expected: 2, found: 3 }));
+    let missing = state.expect("exp_avg", 3).unwrap_err();
+    assert_eq!(missing.to_string(), "missing state entry `exp_avg`");
+}
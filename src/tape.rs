@@ -0,0 +1,294 @@
+use crate::ops::{AddOP, DivOp, MulOp, NegOp, PowOp, SubOp};
+use crate::{Float, Operation};
+
+/// Handle to a value recorded on a [`Tape`]. Handles to leaves stay valid for
+/// the life of the tape; handles to recorded nodes are invalidated by
+/// [`Tape::clear`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TapeVar{
+    slot: Slot,
+    generation: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Slot{
+    Leaf(usize),
+    Node(usize),
+}
+
+// One incoming edge of a recorded node: the local partial derivative is
+// evaluated when the node is recorded, so backward is a single linear sweep.
+struct Edge<T>{
+    from: Slot,
+    partial: T,
+}
+
+/// A gradient tape: an alternative to [`Var`](crate::Var) that stores the whole
+/// graph in a few flat vectors instead of one heap node per operation.
+///
+/// Leaves (parameters) are created with [`Tape::var`] and survive
+/// [`Tape::clear`], so a training loop records the forward pass, calls
+/// [`Tape::backward`], reads and updates the leaves, then clears the recorded
+/// nodes and starts over without reallocating.
+pub struct Tape<T: Float = f64>{
+    leaf_values: Vec<T>,
+    leaf_grads: Vec<T>,
+    values: Vec<T>,
+    grads: Vec<T>,
+    edges: Vec<Edge<T>>,
+    // edges of node i are edges[ends[i - 1]..ends[i]]
+    ends: Vec<usize>,
+    // reused to pass input values to `Operation::op` without allocating
+    scratch: Vec<T>,
+    generation: u32,
+}
+
+impl<T: Float> Default for Tape<T>{
+    fn default() -> Tape<T>{
+        return Tape{
+            leaf_values: Vec::new(),
+            leaf_grads: Vec::new(),
+            values: Vec::new(),
+            grads: Vec::new(),
+            edges: Vec::new(),
+            ends: Vec::new(),
+            scratch: Vec::new(),
+            generation: 0,
+        };
+    }
+}
+
+impl<T: Float> Tape<T>{
+    pub fn new() -> Tape<T>{
+        return Tape::default();
+    }
+
+    /// Adds a leaf that is kept across [`Tape::clear`].
+    pub fn var(&mut self, value: T) -> TapeVar{
+        self.leaf_values.push(value);
+        self.leaf_grads.push(T::zero());
+        return TapeVar{ slot: Slot::Leaf(self.leaf_values.len() - 1), generation: self.generation };
+    }
+
+    /// Records `op` applied to `inputs`, which are handed to it in order.
+    pub fn apply(&mut self, op: &impl Operation<T>, inputs: &[TapeVar]) -> TapeVar{
+        let mut x = std::mem::take(&mut self.scratch);
+        x.clear();
+        x.extend(inputs.iter().map(|&v| self.value(v)));
+        let out = op.op(&x);
+        for (i, v) in inputs.iter().enumerate() {
+            self.edges.push(Edge{ from: v.slot, partial: op.grad(&x, out, i) });
+        }
+        self.scratch = x;
+        self.values.push(out);
+        self.ends.push(self.edges.len());
+        return TapeVar{ slot: Slot::Node(self.values.len() - 1), generation: self.generation };
+    }
+
+    pub fn add(&mut self, a: TapeVar, b: TapeVar) -> TapeVar{
+        return self.apply(&AddOP{}, &[a, b]);
+    }
+
+    pub fn neg(&mut self, a: TapeVar) -> TapeVar{
+        return self.apply(&NegOp{}, &[a]);
+    }
+
+    pub fn sub(&mut self, a: TapeVar, b: TapeVar) -> TapeVar{
+        return self.apply(&SubOp{}, &[a, b]);
+    }
+
+    pub fn mul(&mut self, a: TapeVar, b: TapeVar) -> TapeVar{
+        return self.apply(&MulOp{}, &[a, b]);
+    }
+
+    pub fn div(&mut self, a: TapeVar, b: TapeVar) -> TapeVar{
+        return self.apply(&DivOp{}, &[a, b]);
+    }
+
+    pub fn pow(&mut self, a: TapeVar, p: f64) -> TapeVar{
+        return self.apply(&PowOp{ p }, &[a]);
+    }
+
+    fn check(&self, v: TapeVar) -> Slot{
+        if let Slot::Node(_) = v.slot {
+            assert!(v.generation == self.generation, "TapeVar used after Tape::clear");
+        }
+        return v.slot;
+    }
+
+    pub fn value(&self, v: TapeVar) -> T{
+        return match self.check(v) {
+            Slot::Leaf(i) => self.leaf_values[i],
+            Slot::Node(i) => self.values[i],
+        };
+    }
+
+    pub fn grad(&self, v: TapeVar) -> T{
+        return match self.check(v) {
+            Slot::Leaf(i) => self.leaf_grads[i],
+            Slot::Node(i) => self.grads.get(i).copied().unwrap_or(T::zero()),
+        };
+    }
+
+    /// Overwrites the value of a leaf, e.g. for a parameter update. Nodes that
+    /// were already recorded keep the value they were computed with.
+    pub fn set_value(&mut self, leaf: TapeVar, value: T){
+        match leaf.slot {
+            Slot::Leaf(i) => self.leaf_values[i] = value,
+            Slot::Node(_) => panic!("set_value called on a recorded node"),
+        }
+    }
+
+    /// Backpropagates from `output`. Leaf gradients accumulate across calls like
+    /// [`Var::backward`](crate::Var::backward); gradients of recorded nodes are
+    /// recomputed from scratch.
+    pub fn backward(&mut self, output: TapeVar){
+        let last = match self.check(output) {
+            Slot::Leaf(i) => {
+                self.leaf_grads[i] += T::one();
+                return;
+            }
+            Slot::Node(i) => i,
+        };
+        self.grads.clear();
+        self.grads.resize(self.values.len(), T::zero());
+        self.grads[last] = T::one();
+        // nodes are recorded after their inputs, so reverse recording order is a
+        // reverse topological order
+        for i in (0..=last).rev() {
+            let g = self.grads[i];
+            let start = if i == 0 { 0 } else { self.ends[i - 1] };
+            for e in &self.edges[start..self.ends[i]] {
+                match e.from {
+                    Slot::Leaf(j) => self.leaf_grads[j] += g * e.partial,
+                    Slot::Node(j) => self.grads[j] += g * e.partial,
+                }
+            }
+        }
+    }
+
+    /// Resets every gradient on the tape, leaves included, to zero.
+    pub fn zero_grad(&mut self){
+        self.leaf_grads.iter_mut().for_each(|g| *g = T::zero());
+        self.grads.iter_mut().for_each(|g| *g = T::zero());
+    }
+
+    /// Forgets every recorded node but keeps the leaves, their values and their
+    /// gradients. Capacity is retained, so the next pass does not reallocate.
+    pub fn clear(&mut self){
+        self.values.clear();
+        self.grads.clear();
+        self.edges.clear();
+        self.ends.clear();
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    /// Number of recorded (non-leaf) nodes.
+    pub fn len(&self) -> usize{
+        return self.values.len();
+    }
+
+    pub fn is_empty(&self) -> bool{
+        return self.values.is_empty();
+    }
+}
+
+#[test]
+fn test_tape_matches_var() {
+    use crate::new_var;
+
+    // d = (a*a) * (a*a + a) / b - b^2
+    let mut t = Tape::new();
+    let a = t.var(2.0);
+    let b = t.var(3.0);
+    let aa = t.mul(a, a);
+    let c = t.add(aa, a);
+    let p = t.mul(aa, c);
+    let q = t.div(p, b);
+    let b2 = t.pow(b, 2.0);
+    let d = t.sub(q, b2);
+    t.backward(d);
+
+    let va = new_var(2.0);
+    let vb = new_var(3.0);
+    let vd = &(&va * &va) * &(&(&va * &va) + &va) / &vb - vb.pow(2.0);
+    vd.backward();
+
+    // same function, but the tape shares a*a where the expression recomputes it
+    assert_eq!(t.value(d), vd.value());
+    assert!((t.grad(a) - va.grad.get()).abs() < 1e-12);
+    assert!((t.grad(b) - vb.grad.get()).abs() < 1e-12);
+    assert!((t.grad(a) - 44.0 / 3.0).abs() < 1e-12);
+    assert!((t.grad(aa) - 10.0 / 3.0).abs() < 1e-12);
+}
+
+#[test]
+fn test_tape_apply() {
+    let mut t = Tape::new();
+    let x = t.var(0.5);
+    let y = t.apply(&crate::ops::TanhOp, &[x]);
+    let z = t.neg(y);
+    t.backward(z);
+    assert_eq!(t.value(z), -(0.5f64.tanh()));
+    assert_eq!(t.grad(x), -(1.0 - 0.5f64.tanh().powi(2)));
+}
+
+#[test]
+fn test_tape_reuse() {
+    // fit w in w * x = 6 for x = 2 by gradient descent on (w * x - 6)^2
+    let mut t = Tape::new();
+    let w = t.var(0.0);
+    let x = t.var(2.0);
+    let six = t.var(6.0);
+    for _ in 0..50 {
+        t.clear();
+        t.zero_grad();
+        let wx = t.mul(w, x);
+        let e = t.sub(wx, six);
+        let loss = t.pow(e, 2.0);
+        t.backward(loss);
+        let w_new = t.value(w) - 0.05 * t.grad(w);
+        t.set_value(w, w_new);
+        assert_eq!(t.len(), 3);
+    }
+    assert!((t.value(w) - 3.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_tape_accumulate_and_zero() {
+    let mut t = Tape::new();
+    let a = t.var(4.0);
+    let b = t.var(5.0);
+    let y = t.mul(a, b);
+    t.backward(y);
+    t.backward(y);
+    assert_eq!(t.grad(a), 10.0);
+    t.zero_grad();
+    assert_eq!(t.grad(a), 0.0);
+    assert_eq!(t.grad(y), 0.0);
+    t.backward(y);
+    assert_eq!(t.grad(b), 4.0);
+}
+
+#[test]
+#[should_panic(expected = "after Tape::clear")]
+fn test_tape_stale_handle() {
+    let mut t = Tape::new();
+    let a = t.var(1.0);
+    let y = t.neg(a);
+    t.clear();
+    assert_eq!(t.value(a), 1.0);
+    t.value(y);
+}
+
+#[test]
+fn test_tape_f32() {
+    let mut t: Tape<f32> = Tape::new();
+    let a = t.var(3.0);
+    let b = t.var(4.0);
+    let y = t.mul(a, b);
+    let z = t.pow(y, 0.5);
+    t.backward(z);
+    assert_eq!(t.value(z), 12f32.sqrt());
+    assert!((t.grad(a) - 2.0 / 12f32.sqrt()).abs() < 1e-6);
+}